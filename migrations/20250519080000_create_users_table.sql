@@ -0,0 +1,5 @@
+CREATE TABLE users (
+   user_id uuid PRIMARY KEY,
+   username TEXT NOT NULL UNIQUE,
+   password_hash TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE sessions (
+   session_key TEXT PRIMARY KEY,
+   session_state JSONB NOT NULL,
+   expires_at timestamptz NOT NULL
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,11 @@
+BEGIN;
+    ALTER TABLE subscription_tokens
+        ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+    ALTER TABLE subscription_tokens ADD COLUMN expires_at timestamptz NULL;
+    -- Tokens issued before this migration get the default lifetime.
+    UPDATE subscription_tokens
+    SET expires_at = created_at + interval '24 hours'
+    WHERE expires_at IS NULL;
+    ALTER TABLE subscription_tokens ALTER COLUMN expires_at SET NOT NULL;
+    CREATE INDEX subscription_tokens_expires_at_idx ON subscription_tokens (expires_at);
+COMMIT;
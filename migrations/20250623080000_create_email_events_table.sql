@@ -0,0 +1,9 @@
+CREATE TABLE email_events (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    record_type TEXT NOT NULL,
+    email TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_email_idx ON email_events (email);
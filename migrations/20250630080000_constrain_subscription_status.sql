@@ -0,0 +1,32 @@
+BEGIN;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_status_check CHECK (
+        status IN ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained')
+    );
+
+    -- Keep in sync with `SubscriptionStatus::can_transition_to`.
+    CREATE FUNCTION check_subscription_status_transition() RETURNS trigger AS $$
+    BEGIN
+        IF NEW.status = OLD.status OR (OLD.status, NEW.status) IN (
+            VALUES
+                ('pending_confirmation', 'confirmed'),
+                ('pending_confirmation', 'unsubscribed'),
+                ('pending_confirmation', 'bounced'),
+                ('pending_confirmation', 'complained'),
+                ('confirmed', 'unsubscribed'),
+                ('confirmed', 'bounced'),
+                ('confirmed', 'complained'),
+                ('unsubscribed', 'pending_confirmation'),
+                ('unsubscribed', 'bounced'),
+                ('unsubscribed', 'complained')
+        ) THEN
+            RETURN NEW;
+        END IF;
+        RAISE EXCEPTION 'A subscriber cannot go from % to %.', OLD.status, NEW.status
+            USING ERRCODE = 'check_violation';
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER subscriptions_status_transition
+        BEFORE UPDATE OF status ON subscriptions
+        FOR EACH ROW EXECUTE FUNCTION check_subscription_status_transition();
+COMMIT;
@@ -0,0 +1,16 @@
+-- Emails written in the same transaction as the change they announce,
+-- delivered afterwards by the email dispatcher, which deletes them once
+-- they are sent.
+CREATE TABLE email_outbox (
+    id uuid PRIMARY KEY,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    -- Lets the dispatcher carry on with the trace of the request.
+    traceparent TEXT NULL
+);
+CREATE INDEX email_outbox_execute_after_idx ON email_outbox (execute_after);
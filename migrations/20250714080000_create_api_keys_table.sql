@@ -0,0 +1,10 @@
+-- Keys used by other services to call `/api/v1`. Only a SHA-256 digest of
+-- each key is stored, the key itself is shown once when created.
+CREATE TABLE api_keys (
+    api_key_id uuid PRIMARY KEY,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
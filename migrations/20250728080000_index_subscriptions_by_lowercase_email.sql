@@ -0,0 +1,2 @@
+-- Email provider webhooks look subscribers up case-insensitively
+CREATE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
@@ -0,0 +1,117 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distr::{Alphanumeric, SampleString};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::AuthError;
+
+/// Tells our keys apart from other secrets, e.g. in a secret scanner.
+const API_KEY_PREFIX: &str = "z2p_";
+
+pub struct ApiKey {
+    pub api_key_id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Keys are long random strings rather than passwords picked by humans: a
+/// fast hash is enough, and lets us look keys up by their hash.
+fn hash_api_key(key: &SecretString) -> String {
+    hex::encode(Sha256::digest(key.expose_secret().as_bytes()))
+}
+
+/// Create a new API key and return it alongside its id. The key is not
+/// stored, it cannot be retrieved afterwards.
+#[tracing::instrument(name = "Create API key", skip(pool))]
+pub async fn create_api_key(
+    name: &str,
+    pool: &PgPool,
+) -> Result<(Uuid, SecretString), anyhow::Error> {
+    let api_key_id = Uuid::new_v4();
+    let key = SecretString::from(format!(
+        "{}{}",
+        API_KEY_PREFIX,
+        Alphanumeric.sample_string(&mut rand::rng(), 40)
+    ));
+    sqlx::query!(
+        "INSERT INTO api_keys (api_key_id, name, key_hash) VALUES ($1, $2, $3)",
+        api_key_id,
+        name,
+        hash_api_key(&key),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API key.")?;
+    Ok((api_key_id, key))
+}
+
+/// Returns the id of the API key, unless it is unknown or revoked.
+///
+/// `last_used_at` is only precise to the minute: writing it on every request
+/// would turn each read of the API into a write.
+#[tracing::instrument(name = "Validate API key", skip(key, pool))]
+pub async fn validate_api_key(key: &SecretString, pool: &PgPool) -> Result<Uuid, AuthError> {
+    let api_key_id = sqlx::query_scalar!(
+        r#"
+        WITH api_key AS (
+            SELECT api_key_id, last_used_at
+            FROM api_keys
+            WHERE key_hash = $1 AND revoked_at IS NULL
+        ), touched AS (
+            UPDATE api_keys
+            SET last_used_at = now()
+            FROM api_key
+            WHERE
+                api_keys.api_key_id = api_key.api_key_id AND
+                (api_key.last_used_at IS NULL OR
+                    api_key.last_used_at < now() - interval '1 minute')
+        )
+        SELECT api_key_id AS "api_key_id!"
+        FROM api_key
+        "#,
+        hash_api_key(key),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate an API key.")?;
+    api_key_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown or revoked API key."))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Revoke API key", skip(pool))]
+pub async fn revoke_api_key(api_key_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = now()
+        WHERE api_key_id = $1 AND revoked_at IS NULL
+        "#,
+        api_key_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke an API key.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "List API keys", skip(pool))]
+pub async fn list_api_keys(pool: &PgPool) -> Result<Vec<ApiKey>, anyhow::Error> {
+    let api_keys = sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT api_key_id, name, created_at, last_used_at, revoked_at
+        FROM api_keys
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to list API keys.")?;
+    Ok(api_keys)
+}
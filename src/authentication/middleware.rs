@@ -0,0 +1,225 @@
+use actix_web::{
+    FromRequest, HttpMessage, HttpResponse,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderValue},
+    },
+    middleware::Next,
+    web::Data,
+};
+use anyhow::Context;
+use base64::Engine;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{AuthError, Credentials, validate_api_key, validate_credentials};
+use crate::{
+    configuration::WebhookSettings,
+    problem_details::ProblemDetails,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::ops::Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Only let through requests carrying valid HTTP Basic credentials.
+///
+/// The id of the authenticated user is stored in the request extensions,
+/// handlers can get it back with `web::ReqData<UserId>`.
+pub async fn reject_anonymous_callers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let credentials =
+        basic_authentication(req.headers()).map_err(|e| unauthorized(e, "publish"))?;
+
+    let pool = req
+        .app_data::<Data<PgPool>>()
+        .expect("The connection pool is not registered as application data.");
+    match validate_credentials(credentials, pool).await {
+        Ok(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
+        }
+        Err(AuthError::InvalidCredentials(e)) => Err(unauthorized(e, "publish")),
+        Err(AuthError::UnexpectedError(e)) => Err(actix_web::error::ErrorInternalServerError(
+            AuthError::UnexpectedError(e),
+        )),
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ApiKeyId(Uuid);
+
+impl std::fmt::Display for ApiKeyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::ops::Deref for ApiKeyId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Only let through requests carrying a valid API key as a bearer token.
+///
+/// The id of the key is stored in the request extensions, handlers can get
+/// it back with `web::ReqData<ApiKeyId>`.
+pub async fn reject_invalid_api_keys(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let key = bearer_token(req.headers()).map_err(api_key_unauthorized)?;
+
+    let pool = req
+        .app_data::<Data<PgPool>>()
+        .expect("The connection pool is not registered as application data.");
+    match validate_api_key(&key, pool).await {
+        Ok(api_key_id) => {
+            req.extensions_mut().insert(ApiKeyId(api_key_id));
+            next.call(req).await
+        }
+        Err(AuthError::InvalidCredentials(e)) => Err(api_key_unauthorized(e)),
+        Err(AuthError::UnexpectedError(e)) => Err(actix_web::error::ErrorInternalServerError(
+            AuthError::UnexpectedError(e),
+        )),
+    }
+}
+
+/// Only let through requests belonging to a logged-in session, everybody
+/// else is sent to the login form.
+pub async fn reject_anonymous_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    match session.get_user_id().map_err(e500)? {
+        Some(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
+        }
+        None => {
+            let response = see_other("/login");
+            let e = anyhow::anyhow!("The user has not logged in");
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// Only let through webhook calls carrying the credentials in
+/// `WebhookSettings`.
+pub async fn reject_unknown_webhook_callers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let credentials =
+        basic_authentication(req.headers()).map_err(|e| unauthorized(e, "webhooks"))?;
+
+    let settings = req
+        .app_data::<Data<WebhookSettings>>()
+        .expect("The webhook settings are not registered as application data.");
+    // An empty password means the secret was never configured: lock everybody out.
+    let is_valid = !settings.password.expose_secret().is_empty()
+        & constant_time_eq(&credentials.username, &settings.username)
+        & constant_time_eq(
+            credentials.password.expose_secret(),
+            settings.password.expose_secret(),
+        );
+    if !is_valid {
+        let e = anyhow::anyhow!("Invalid webhook credentials.");
+        return Err(unauthorized(e, "webhooks"));
+    }
+    next.call(req).await
+}
+
+/// Compare two secrets without leaking, through timing, how long their
+/// common prefix is.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn unauthorized(e: anyhow::Error, realm: &str) -> actix_web::Error {
+    let mut response = HttpResponse::Unauthorized().finish();
+    let header_value = HeaderValue::from_str(&format!(r#"Basic realm="{}""#, realm)).unwrap();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, header_value);
+    InternalError::from_response(AuthError::InvalidCredentials(e), response).into()
+}
+
+/// Like `unauthorized`, with the JSON body API clients expect.
+fn api_key_unauthorized(e: anyhow::Error) -> actix_web::Error {
+    let mut response =
+        ProblemDetails::new(StatusCode::UNAUTHORIZED, "A valid API key is required.").response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    InternalError::from_response(AuthError::InvalidCredentials(e), response).into()
+}
+
+fn bearer_token(headers: &HeaderMap) -> Result<SecretString, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing.")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let token = header_value
+        .strip_prefix("Bearer ")
+        .context("The authorization scheme was not 'Bearer'.")?;
+    Ok(SecretString::from(token.trim().to_string()))
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing.")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A username and a password must be provided in 'Basic' auth.")?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: SecretString::from(password.to_string()),
+    })
+}
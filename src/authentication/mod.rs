@@ -0,0 +1,12 @@
+mod api_key;
+mod middleware;
+mod password;
+
+pub use api_key::{ApiKey, create_api_key, list_api_keys, revoke_api_key, validate_api_key};
+pub use middleware::{
+    ApiKeyId, UserId, reject_anonymous_callers, reject_anonymous_users, reject_invalid_api_keys,
+    reject_unknown_webhook_callers,
+};
+pub use password::{
+    AuthError, Credentials, change_password, create_initial_admin, validate_credentials,
+};
@@ -0,0 +1,195 @@
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::Context;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::telemetry::trace_context_headers;
+
+/// Tells humans and bots apart, from the token a CAPTCHA widget adds to
+/// our forms.
+#[async_trait::async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool, anyhow::Error>;
+}
+
+/// Checks tokens against a `siteverify` API, as offered by hCaptcha and
+/// Cloudflare Turnstile.
+pub struct SiteVerifyCaptcha {
+    http_client: Client,
+    verify_url: String,
+    secret_key: SecretString,
+}
+
+impl SiteVerifyCaptcha {
+    pub fn new(verify_url: String, secret_key: SecretString, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            verify_url,
+            secret_key,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct VerifyRequest<'a> {
+    secret: &'a str,
+    response: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remoteip: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct VerifyResponse {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CaptchaVerifier for SiteVerifyCaptcha {
+    #[tracing::instrument(name = "Verifying a CAPTCHA token", skip(self, token))]
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool, anyhow::Error> {
+        let request = VerifyRequest {
+            secret: self.secret_key.expose_secret(),
+            response: token,
+            remoteip: remote_ip.map(|ip| ip.to_string()),
+        };
+        let response: VerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&request)
+            .headers(trace_context_headers())
+            .send()
+            .await
+            .context("Failed to reach the CAPTCHA provider.")?
+            .error_for_status()
+            .context("The CAPTCHA provider rejected our request.")?
+            .json()
+            .await
+            .context("Failed to parse the CAPTCHA provider's response.")?;
+        if !response.success {
+            tracing::info!(error_codes = ?response.error_codes, "CAPTCHA verification failed.");
+        }
+        Ok(response.success)
+    }
+}
+
+/// The CAPTCHA sign-ups have to pass, if any.
+pub struct Captcha(Option<Box<dyn CaptchaVerifier>>);
+
+impl Captcha {
+    pub fn new(verifier: impl CaptchaVerifier + 'static) -> Self {
+        Self(Some(Box::new(verifier)))
+    }
+
+    pub fn disabled() -> Self {
+        Self(None)
+    }
+
+    /// Whether the client passed the CAPTCHA. A missing token fails, unless
+    /// no CAPTCHA is required.
+    pub async fn check(
+        &self,
+        token: Option<&str>,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<bool, anyhow::Error> {
+        match (&self.0, token) {
+            (None, _) => Ok(true),
+            (Some(_), None) => Ok(false),
+            (Some(verifier), Some(token)) => verifier.verify(token, remote_ip).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::SecretString;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, method},
+    };
+
+    use super::{Captcha, CaptchaVerifier, SiteVerifyCaptcha};
+
+    fn verifier(server: &MockServer) -> SiteVerifyCaptcha {
+        SiteVerifyCaptcha::new(
+            format!("{}/siteverify", server.uri()),
+            SecretString::from("my-secret"),
+            Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn verify_sends_the_secret_and_the_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("secret=my-secret"))
+            .and(body_string_contains("response=a-token"))
+            .and(body_string_contains("remoteip=203.0.113.1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let outcome = verifier(&server)
+            .verify("a-token", Some("203.0.113.1".parse().unwrap()))
+            .await;
+
+        assert_ok_eq!(outcome, true);
+    }
+
+    #[tokio::test]
+    async fn verify_fails_tokens_the_provider_rejects() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "error-codes": ["invalid-input-response"]
+            })))
+            .mount(&server)
+            .await;
+
+        let outcome = verifier(&server).verify("a-token", None).await;
+
+        assert_ok_eq!(outcome, false);
+    }
+
+    #[tokio::test]
+    async fn verify_errors_if_the_provider_is_down() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let outcome = verifier(&server).verify("a-token", None).await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_disabled_captcha_lets_everybody_through() {
+        assert_ok_eq!(Captcha::disabled().check(None, None).await, true);
+    }
+
+    #[tokio::test]
+    async fn a_missing_token_fails_without_calling_the_provider() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let outcome = Captcha::new(verifier(&server)).check(None, None).await;
+
+        assert_ok_eq!(outcome, false);
+    }
+}
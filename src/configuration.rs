@@ -1,18 +1,40 @@
-use std::{sync::OnceLock, time::Duration};
+use std::{path::PathBuf, sync::OnceLock, time::Duration};
 
 use config::{Config, ConfigError, File};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use tera::Tera;
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    authentication::Credentials,
+    captcha::SiteVerifyCaptcha,
+    domain::{SubscriberEmail, SubscriberEmailError},
+    email_client::{
+        EmailClient, FileEmailSender, HttpApiEmailSender, PostmarkEmailSender, RetryPolicy,
+        SmtpEmailSender,
+    },
+};
+
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub webhooks: WebhookSettings,
+    pub health: HealthSettings,
+    pub telemetry: TelemetrySettings,
+    /// Sign-ups go through a CAPTCHA only if set.
+    #[serde(default)]
+    pub captcha: Option<CaptchaSettings>,
+    /// Created at startup if there is no user yet, so that a fresh
+    /// deployment can be logged into.
+    #[serde(default)]
+    pub initial_admin: Option<InitialAdminSettings>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -21,6 +43,147 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    /// Signs the session and flash message cookies, at least 64 bytes long.
+    pub hmac_secret: SecretString,
+    /// Serve `/metrics` on this port, which must not be exposed publicly.
+    /// Metrics are not served at all if unset.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub admin_port: Option<u16>,
+    /// How long in-flight requests get to complete once we are asked to
+    /// shut down.
+    pub shutdown_grace_period_seconds: u64,
+    pub rate_limit: RateLimitSettings,
+    /// Run the issue delivery worker, the email dispatcher and the purge of
+    /// unconfirmed subscriptions alongside the server.
+    pub background_workers: bool,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_period_seconds)
+    }
+}
+
+/// Limits on the public endpoints that send emails, `POST /subscriptions`
+/// and `POST /subscriptions/resend`, shared between the two.
+///
+/// Requests are counted in the memory of each instance: with several
+/// instances running, a client gets these limits from each of them.
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Requests a single client IP may make per period.
+    pub requests_per_ip: u32,
+    /// Requests targeting a single email address per period.
+    pub requests_per_email: u32,
+    pub period_seconds: u64,
+    /// Headers our reverse proxy sets to the client IP, e.g. `Fly-Client-IP`,
+    /// checked in order before falling back to the peer address. Only list
+    /// headers the proxy overwrites: clients can set any other.
+    #[serde(default)]
+    pub trusted_proxy_headers: Vec<String>,
+}
+
+impl RateLimitSettings {
+    pub fn period(&self) -> Duration {
+        Duration::from_secs(self.period_seconds)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    /// How long a confirmation link stays valid.
+    pub confirmation_token_ttl_hours: u64,
+    /// Expired tokens, and pending subscribers left with none, are purged
+    /// once they are this old.
+    pub unconfirmed_retention_hours: u64,
+    pub cleanup_interval_seconds: u64,
+    /// Where to send subscribers once they confirmed, e.g. our marketing
+    /// site. They see our own success page if unset.
+    pub confirmation_redirect_url: Option<String>,
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> Duration {
+        Duration::from_secs(self.confirmation_token_ttl_hours * 60 * 60)
+    }
+
+    pub fn unconfirmed_retention(&self) -> Duration {
+        Duration::from_secs(self.unconfirmed_retention_hours * 60 * 60)
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
+/// Credentials our email provider sends, with HTTP Basic auth, when calling
+/// our webhooks.
+#[derive(Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// What `/health/ready` checks before reporting the instance as ready.
+#[derive(Deserialize, Clone)]
+pub struct HealthSettings {
+    /// How long each dependency gets to answer.
+    pub timeout_milliseconds: u64,
+    /// Also ask the email provider whether it accepts our credentials.
+    pub probe_email_provider: bool,
+}
+
+impl HealthSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+/// Where to export our traces, if anywhere.
+#[derive(Deserialize, Clone)]
+pub struct TelemetrySettings {
+    /// An OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    pub otlp_endpoint: Option<String>,
+    /// Share of the traces started here that get exported, between 0 and 1.
+    /// Traces started upstream follow the caller's decision.
+    pub sampling_ratio: f64,
+}
+
+/// A `siteverify` API checking the tokens of our CAPTCHA widget.
+#[derive(Deserialize, Clone)]
+pub struct CaptchaSettings {
+    /// e.g. `https://api.hcaptcha.com/siteverify` or
+    /// `https://challenges.cloudflare.com/turnstile/v0/siteverify`.
+    pub verify_url: String,
+    pub secret_key: SecretString,
+    pub timeout_milliseconds: u64,
+}
+
+impl CaptchaSettings {
+    pub fn verifier(self) -> SiteVerifyCaptcha {
+        SiteVerifyCaptcha::new(
+            self.verify_url,
+            self.secret_key,
+            Duration::from_millis(self.timeout_milliseconds),
+        )
+    }
+}
+
+/// e.g. set with `APP_INITIAL_ADMIN__USERNAME` and
+/// `APP_INITIAL_ADMIN__PASSWORD`. Change the password after the first login.
+#[derive(Deserialize, Clone)]
+pub struct InitialAdminSettings {
+    pub username: String,
+    pub password: SecretString,
+}
+
+impl InitialAdminSettings {
+    pub fn credentials(self) -> Credentials {
+        Credentials {
+            username: self.username,
+            password: self.password,
+        }
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -44,7 +207,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
             .database(&self.database_name)
@@ -106,16 +269,98 @@ impl TryFrom<String> for Environment {
     }
 }
 
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailProvider {
+    #[default]
+    Postmark,
+    Smtp,
+    /// A SendGrid/Mailgun-style JSON API, `base_url` being its send endpoint.
+    HttpApi,
+    /// Writes emails to `email_directory`, or to stdout, for local development.
+    File,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub provider: EmailProvider,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    pub retry: RetrySettings,
+    /// Required by the `smtp` provider.
+    pub smtp: Option<SmtpSettings>,
+    pub email_directory: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    pub require_tls: bool,
+}
+
+/// Applies both to confirmation emails, rescheduled in the outbox, and to
+/// newsletter issues, rescheduled in the delivery queue.
+#[derive(Deserialize, Clone)]
+pub struct RetrySettings {
+    /// Including the first attempt.
+    pub max_attempts: u32,
+    pub initial_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            initial_backoff: Duration::from_millis(self.initial_backoff_milliseconds),
+            max_backoff: Duration::from_millis(self.max_backoff_milliseconds),
+        }
+    }
 }
 
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn client(self) -> EmailClient {
+        let sender = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let retry_policy = self.retry.policy();
+        let client = match self.provider {
+            EmailProvider::Postmark => EmailClient::new(
+                sender,
+                PostmarkEmailSender::new(self.base_url, self.authorization_token, timeout),
+            ),
+            EmailProvider::Smtp => {
+                let smtp = self.smtp.expect("Missing SMTP settings.");
+                let credentials = smtp.username.zip(smtp.password);
+                let backend = SmtpEmailSender::new(
+                    &smtp.host,
+                    smtp.port,
+                    credentials,
+                    smtp.require_tls,
+                    timeout,
+                )
+                .expect("Invalid SMTP settings.");
+                EmailClient::new(sender, backend)
+            }
+            EmailProvider::HttpApi => EmailClient::new(
+                sender,
+                HttpApiEmailSender::new(self.base_url, self.authorization_token, timeout),
+            ),
+            EmailProvider::File => EmailClient::new(
+                sender,
+                FileEmailSender::new(self.email_directory.map(PathBuf::from)),
+            ),
+        };
+        client.with_retry_policy(retry_policy)
+    }
+
+    pub fn sender(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
@@ -123,11 +368,19 @@ impl EmailClientSettings {
         Duration::from_millis(self.timeout_milliseconds)
     }
 }
+
 pub fn email_templates() -> &'static Tera {
     static EMAIL_TEMPLATES: OnceLock<Tera> = OnceLock::new();
     EMAIL_TEMPLATES.get_or_init(|| {
-        let mut tera = Tera::new("templates/**/*").expect("Unable to load email templates.");
+        let mut tera = Tera::new("templates/emails/**/*").expect("Unable to load email templates.");
         tera.autoescape_on(vec![".html", ".sql"]);
         tera
     })
 }
+
+/// The HTML pages under `templates/pages`, e.g. `confirmation/success.html`.
+pub fn page_templates() -> &'static Tera {
+    static PAGE_TEMPLATES: OnceLock<Tera> = OnceLock::new();
+    PAGE_TEMPLATES
+        .get_or_init(|| Tera::new("templates/pages/**/*").expect("Unable to load page templates."))
+}
@@ -1,7 +1,9 @@
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
 
-pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use new_subscriber::{InvalidField, NewSubscriber, ValidationErrors};
+pub use subscriber_email::{SubscriberEmail, SubscriberEmailError};
+pub use subscriber_name::{SubscriberName, SubscriberNameError};
+pub use subscription_status::SubscriptionStatus;
@@ -1,6 +1,115 @@
-use super::{SubscriberEmail, SubscriberName};
+use super::{SubscriberEmail, SubscriberEmailError, SubscriberName, SubscriberNameError};
 
+#[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
 }
+
+impl NewSubscriber {
+    /// Validate every field, reporting all the invalid ones rather than
+    /// stopping at the first.
+    pub fn parse(name: String, email: String) -> Result<Self, ValidationErrors> {
+        match (SubscriberName::parse(name), SubscriberEmail::parse(email)) {
+            (Ok(name), Ok(email)) => Ok(Self { email, name }),
+            (name, email) => Err(ValidationErrors(
+                [
+                    name.err().map(InvalidField::Name),
+                    email.err().map(InvalidField::Email),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+            )),
+        }
+    }
+}
+
+/// A field of a new subscriber that failed validation.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum InvalidField {
+    #[error(transparent)]
+    Name(#[from] SubscriberNameError),
+    #[error(transparent)]
+    Email(#[from] SubscriberEmailError),
+}
+
+impl InvalidField {
+    /// The name of the field, as in our forms.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::Name(_) => "name",
+            Self::Email(_) => "email",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Name(e) => e.code(),
+            Self::Email(e) => e.code(),
+        }
+    }
+}
+
+/// Every invalid field of a new subscriber, never empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(Vec<InvalidField>);
+
+impl ValidationErrors {
+    pub fn fields(&self) -> &[InvalidField] {
+        &self.0
+    }
+}
+
+impl From<InvalidField> for ValidationErrors {
+    fn from(value: InvalidField) -> Self {
+        Self(vec![value])
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        f.write_str(&messages.join(" "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{InvalidField, NewSubscriber};
+    use crate::domain::{SubscriberEmailError, SubscriberNameError};
+
+    #[test]
+    fn valid_fields_are_parsed_successfully() {
+        assert_ok!(NewSubscriber::parse(
+            "Ursula Le Guin".into(),
+            "ursula_le_guin@gmail.com".into()
+        ));
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let errors = assert_err!(NewSubscriber::parse("".into(), "not-an-email".into()));
+
+        assert_eq!(
+            errors.fields(),
+            [
+                InvalidField::Name(SubscriberNameError::Empty),
+                InvalidField::Email(SubscriberEmailError::InvalidSyntax),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_invalid_fields_are_reported() {
+        let errors = assert_err!(NewSubscriber::parse("Ursula Le Guin".into(), "".into()));
+
+        assert_eq!(errors.fields().len(), 1);
+        assert_eq!(errors.fields()[0].field(), "email");
+        assert_eq!(errors.fields()[0].code(), "empty");
+    }
+}
@@ -3,12 +3,34 @@ use validator::ValidateEmail;
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
+/// Why a subscriber email was rejected.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SubscriberEmailError {
+    #[error("The email address is empty.")]
+    Empty,
+    #[error("The email address is not valid.")]
+    InvalidSyntax,
+}
+
+impl SubscriberEmailError {
+    /// Identifies the error regardless of the wording of its message, e.g.
+    /// for clients to show a translated one.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::InvalidSyntax => "invalid_syntax",
+        }
+    }
+}
+
 impl SubscriberEmail {
-    pub fn parse(value: String) -> Result<SubscriberEmail, String> {
-        if value.validate_email() {
+    pub fn parse(value: String) -> Result<SubscriberEmail, SubscriberEmailError> {
+        if value.trim().is_empty() {
+            Err(SubscriberEmailError::Empty)
+        } else if value.validate_email() {
             Ok(Self(value))
         } else {
-            Err(format!("{} is not a valid subscriber email.", value))
+            Err(SubscriberEmailError::InvalidSyntax)
         }
     }
 }
@@ -19,9 +41,15 @@ impl AsRef<str> for SubscriberEmail {
     }
 }
 
+impl std::fmt::Display for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use claims::assert_err;
+    use claims::assert_err_eq;
     use fake::{Fake, faker::internet::en::SafeEmail};
     use rand::{SeedableRng, rngs::StdRng};
 
@@ -36,24 +64,30 @@ mod tests {
         }
     }
 
-    use crate::domain::SubscriberEmail;
+    use crate::domain::{SubscriberEmail, SubscriberEmailError};
 
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_err_eq!(SubscriberEmail::parse(email), SubscriberEmailError::Empty);
     }
 
     #[test]
     fn email_missing_at_symbol_is_rejected() {
         let email = "somemissing.com".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_err_eq!(
+            SubscriberEmail::parse(email),
+            SubscriberEmailError::InvalidSyntax
+        );
     }
 
     #[test]
     fn email_missing_subject_is_rejected() {
         let email = "@somemissing.com".to_string();
-        assert_err!(SubscriberEmail::parse(email));
+        assert_err_eq!(
+            SubscriberEmail::parse(email),
+            SubscriberEmailError::InvalidSyntax
+        );
     }
 
     #[quickcheck_macros::quickcheck]
@@ -1,17 +1,44 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+/// The longest name we accept, in graphemes.
+const MAX_LENGTH: usize = 256;
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
+/// Why a subscriber name was rejected.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SubscriberNameError {
+    #[error("The name is empty.")]
+    Empty,
+    #[error("The name is longer than {max_length} characters.")]
+    TooLong { max_length: usize },
+    #[error("The name contains forbidden characters: {}", .0.iter().collect::<String>())]
+    ForbiddenCharacters(Vec<char>),
+}
+
+impl SubscriberNameError {
+    /// Identifies the error regardless of the wording of its message, e.g.
+    /// for clients to show a translated one.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::TooLong { .. } => "too_long",
+            Self::ForbiddenCharacters(_) => "forbidden_characters",
+        }
+    }
+}
+
 impl SubscriberName {
     /// Returns an instance of `SubscriberName` if the input satisfies all
-    /// our validation constraints on subscriber names.  
-    /// It panics otherwise.
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
+    /// our validation constraints on subscriber names.
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
         // `.trim()` returns a view over the input `s` without trailing
         // whitespace-like characters.
-        // `.is_empty` checks if the view contains any character.
-        let is_empty_or_whitespace = s.trim().is_empty();
+        if s.trim().is_empty() {
+            return Err(SubscriberNameError::Empty);
+        }
 
         // A grapheme is defined by the Unicode standard as a "user-perceived"
         // character: `å` is a single grapheme, but it is composed of two characters
@@ -20,19 +47,26 @@ impl SubscriberName {
         // `graphemes` returns an iterator over the graphemes in the input `s`.
         // `true` specifies that we want to use the extended grapheme definition set,
         // the recommended one.
+        if s.graphemes(true).count() > MAX_LENGTH {
+            return Err(SubscriberNameError::TooLong {
+                max_length: MAX_LENGTH,
+            });
+        }
 
-        let is_too_long = s.graphemes(true).count() > 256;
-
-        // Iterate over all characters in the input `s` to check if any of them
-        // matches one of the characters in the forbidden array.
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
-
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
-        } else {
-            Ok(Self(s))
+        // Report each forbidden character once, in the order they appear.
+        let mut forbidden_characters = Vec::new();
+        for c in s.chars().filter(|c| FORBIDDEN_CHARACTERS.contains(c)) {
+            if !forbidden_characters.contains(&c) {
+                forbidden_characters.push(c);
+            }
+        }
+        if !forbidden_characters.is_empty() {
+            return Err(SubscriberNameError::ForbiddenCharacters(
+                forbidden_characters,
+            ));
         }
+
+        Ok(Self(s))
     }
 }
 
@@ -44,9 +78,9 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use claims::{assert_err, assert_ok};
+    use claims::{assert_err, assert_err_eq, assert_ok};
 
-    use crate::domain::SubscriberName;
+    use crate::domain::{SubscriberName, SubscriberNameError};
 
     #[test]
     fn a_256_grapheme_long_name_is_valid() {
@@ -57,19 +91,22 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(
+            SubscriberName::parse(name),
+            SubscriberNameError::TooLong { max_length: 256 }
+        );
     }
 
     #[test]
     fn whitespace_only_names_are_rejected() {
         let name = " ".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::Empty);
     }
 
     #[test]
     fn empty_string_is_rejected() {
         let name = "".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::Empty);
     }
 
     #[test]
@@ -80,6 +117,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn each_forbidden_character_is_reported_once() {
+        let name = "<b>Ursula</b>".to_string();
+        assert_err_eq!(
+            SubscriberName::parse(name),
+            SubscriberNameError::ForbiddenCharacters(vec!['<', '>', '/'])
+        );
+    }
+
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "Ursula Le Guin".to_string();
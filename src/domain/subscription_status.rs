@@ -0,0 +1,165 @@
+use sqlx::{
+    Decode, Postgres, Type,
+    error::BoxDynError,
+    postgres::{PgTypeInfo, PgValueRef},
+};
+
+/// Where a subscriber stands in their lifecycle, stored as TEXT in
+/// `subscriptions.status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// Their address hard-bounced.
+    Bounced,
+    /// They marked one of our emails as spam.
+    Complained,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Bounced => "bounced",
+            Self::Complained => "complained",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            "bounced" => Ok(Self::Bounced),
+            "complained" => Ok(Self::Complained),
+            other => Err(format!("{} is not a valid subscription status.", other)),
+        }
+    }
+
+    /// Whether a subscriber can move from `self` to `next`. Staying put is
+    /// always allowed.
+    ///
+    /// Bounced and complained are final: we never mail those addresses again.
+    /// This mirrors the `subscriptions_status_transition` trigger.
+    pub fn can_transition_to(&self, next: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+
+        *self == next
+            || matches!(
+                (self, next),
+                (
+                    PendingConfirmation,
+                    Confirmed | Unsubscribed | Bounced | Complained
+                ) | (Confirmed, Unsubscribed | Bounced | Complained)
+                    | (Unsubscribed, PendingConfirmation | Bounced | Complained)
+            )
+    }
+
+    /// Like `can_transition_to`, with an error describing the illegal move.
+    pub fn transition_to(&self, next: SubscriptionStatus) -> Result<SubscriptionStatus, String> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(format!(
+                "A subscriber cannot go from {} to {}.",
+                self.as_str(),
+                next.as_str()
+            ))
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Read with `status AS "status: SubscriptionStatus"`. Queries bind
+// `as_str()` rather than the enum, so there is no `Encode`.
+impl Type<Postgres> for SubscriptionStatus {
+    fn type_info() -> PgTypeInfo {
+        <&str as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <&str as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for SubscriptionStatus {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let s = <&str as Decode<Postgres>>::decode(value)?;
+        Ok(Self::parse(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::SubscriptionStatus::{self, *};
+
+    const ALL: [SubscriptionStatus; 5] = [
+        PendingConfirmation,
+        Confirmed,
+        Unsubscribed,
+        Bounced,
+        Complained,
+    ];
+
+    #[test]
+    fn statuses_round_trip_through_their_text_form() {
+        for status in ALL {
+            assert_ok_eq!(SubscriptionStatus::parse(status.as_str()), status);
+        }
+    }
+
+    #[test]
+    fn statuses_are_serialized_as_their_text_form() {
+        for status in ALL {
+            assert_eq!(
+                serde_json::to_value(status).unwrap(),
+                serde_json::json!(status.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(SubscriptionStatus::parse("pending"));
+    }
+
+    #[test]
+    fn pending_subscribers_can_confirm_but_not_come_back_to_pending() {
+        assert!(PendingConfirmation.can_transition_to(Confirmed));
+        assert!(!Confirmed.can_transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn unsubscribed_subscribers_can_subscribe_again() {
+        assert!(Confirmed.can_transition_to(Unsubscribed));
+        assert!(Unsubscribed.can_transition_to(PendingConfirmation));
+        assert!(!Unsubscribed.can_transition_to(Confirmed));
+    }
+
+    #[test]
+    fn bounced_and_complained_are_final() {
+        for from in [Bounced, Complained] {
+            for to in ALL.into_iter().filter(|to| *to != from) {
+                assert_err!(from.transition_to(to));
+            }
+        }
+    }
+
+    #[test]
+    fn staying_put_is_always_allowed() {
+        for status in ALL {
+            assert_ok_eq!(status.transition_to(status), status);
+        }
+    }
+}
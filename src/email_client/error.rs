@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use reqwest::{
+    Response, StatusCode,
+    header::{HeaderMap, RETRY_AFTER},
+};
+
+use crate::routes::error_chain_fmt;
+
+#[derive(thiserror::Error)]
+pub enum SendEmailError {
+    /// The provider asked us to slow down, and possibly told us for how long.
+    #[error("The email provider is rate limiting us.")]
+    RateLimited { retry_after: Option<Duration> },
+    /// Timeouts, connection failures, 5xx: trying again later may work.
+    #[error("Failed to send the email, trying again may succeed.")]
+    Transient(#[source] anyhow::Error),
+    /// The email was rejected, e.g. because of an invalid recipient: sending
+    /// it again will fail the same way.
+    #[error("The email was rejected.")]
+    Permanent(#[source] anyhow::Error),
+    /// The provider refused our credentials. Nothing gets through until the
+    /// configuration is fixed, but the email itself is fine.
+    #[error("The email provider rejected our credentials.")]
+    Unauthorized(#[source] anyhow::Error),
+}
+
+impl std::fmt::Debug for SendEmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl SendEmailError {
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::Permanent(_))
+    }
+}
+
+/// Classify the outcome of a call to an HTTP email API.
+pub fn check_response(outcome: Result<Response, reqwest::Error>) -> Result<(), SendEmailError> {
+    let response = outcome.map_err(|e| SendEmailError::Transient(e.into()))?;
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(SendEmailError::RateLimited {
+            retry_after: retry_after(response.headers()),
+        });
+    }
+    match response.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT => {
+            Err(SendEmailError::Transient(e.into()))
+        }
+        Err(e) if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN => {
+            Err(SendEmailError::Unauthorized(e.into()))
+        }
+        // E.g. 422 for invalid or inactive recipients, 413 for an email
+        // that is too large
+        Err(e) => Err(SendEmailError::Permanent(e.into())),
+    }
+}
+
+/// Only the delay-seconds form of `Retry-After` is supported, email
+/// providers don't send HTTP dates.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
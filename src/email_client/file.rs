@@ -0,0 +1,97 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::Context;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{Email, EmailSender, SendEmailError};
+
+/// Doesn't deliver anything: writes each email as an `.eml` file in
+/// `directory`, or to stdout if there is none. Meant for local development.
+pub struct FileEmailSender {
+    directory: Option<PathBuf>,
+}
+
+impl FileEmailSender {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self { directory }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FileEmailSender {
+    fn provider(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        let message = email
+            .to_message()
+            .map_err(SendEmailError::Permanent)?
+            .formatted();
+        self.write(&message)
+            .await
+            .map_err(SendEmailError::Transient)
+    }
+}
+
+impl FileEmailSender {
+    async fn write(&self, message: &[u8]) -> Result<(), anyhow::Error> {
+        match &self.directory {
+            Some(directory) => {
+                tokio::fs::create_dir_all(directory)
+                    .await
+                    .context("Failed to create the email directory.")?;
+                let filename = format!(
+                    "{}-{}.eml",
+                    Utc::now().format("%Y%m%dT%H%M%S"),
+                    Uuid::new_v4()
+                );
+                tokio::fs::write(directory.join(filename), message)
+                    .await
+                    .context("Failed to write an email to the email directory.")?;
+            }
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(message)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, FileEmailSender},
+    };
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_to_the_email_directory() {
+        // Arrange
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let sender = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let email_client = EmailClient::new(sender, FileEmailSender::new(Some(directory.clone())));
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient, "Welcome!", "<p>Hello</p>", "Hello", None)
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let files: Vec<_> = std::fs::read_dir(&directory).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let path = files[0].as_ref().unwrap().path();
+        assert_eq!(path.extension().unwrap(), "eml");
+        let message = std::fs::read_to_string(&path).unwrap();
+        assert!(message.contains("Subject: Welcome!"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+
+use super::{Email, EmailSender, SendEmailError, error::check_response};
+use crate::telemetry::trace_context_headers;
+
+/// Sends emails through a SendGrid/Mailgun-style JSON API: a `POST` to
+/// `url`, authenticated with a bearer token.
+pub struct HttpApiEmailSender {
+    http_client: Client,
+    url: String,
+    api_key: SecretString,
+}
+
+impl HttpApiEmailSender {
+    pub fn new(url: String, api_key: SecretString, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            url,
+            api_key,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for HttpApiEmailSender {
+    fn provider(&self) -> &'static str {
+        "http_api"
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        let list_unsubscribe_headers = email.list_unsubscribe_headers();
+        let request_body = SendEmailRequest {
+            from: email.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: email.subject,
+            html: email.html_content,
+            text: email.text_content,
+            headers: list_unsubscribe_headers
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect(),
+        };
+
+        let outcome = self
+            .http_client
+            .post(&self.url)
+            .bearer_auth(self.api_key.expose_secret())
+            .json(&request_body)
+            .headers(trace_context_headers())
+            .send()
+            .await;
+        check_response(outcome)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    headers: HashMap<&'a str, &'a str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        Fake,
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+    };
+    use secrecy::SecretString;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{any, body_partial_json, header, method, path},
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, HttpApiEmailSender},
+    };
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            HttpApiEmailSender::new(
+                format!("{}/v3/mail/send", base_url),
+                SecretString::from("my-api-key"),
+                Duration::from_millis(200),
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_posts_the_message_as_json_with_a_bearer_token() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let subject: String = Sentence(1..2).fake();
+
+        Mock::given(method("POST"))
+            .and(path("/v3/mail/send"))
+            .and(header("Authorization", "Bearer my-api-key"))
+            .and(body_partial_json(serde_json::json!({
+                "to": recipient.as_ref(),
+                "subject": subject,
+                "headers": {
+                    "List-Unsubscribe": "<https://example.com/unsubscribe>",
+                    "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+                },
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &recipient,
+                &subject,
+                &Paragraph(1..10).fake::<String>(),
+                &Paragraph(1..10).fake::<String>(),
+                Some("https://example.com/unsubscribe"),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "subject", "<p>html</p>", "text", None)
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+}
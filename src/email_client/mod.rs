@@ -0,0 +1,159 @@
+mod error;
+mod file;
+mod http_api;
+mod postmark;
+mod retry;
+mod smtp;
+
+pub use error::SendEmailError;
+pub use file::FileEmailSender;
+pub use http_api::HttpApiEmailSender;
+pub use postmark::PostmarkEmailSender;
+pub use retry::{NextStep, RetryPolicy};
+pub use smtp::SmtpEmailSender;
+
+use anyhow::Context;
+use lettre::{
+    Message,
+    message::{
+        MultiPart,
+        header::{HeaderName, HeaderValue},
+    },
+};
+
+use crate::{domain::SubscriberEmail, metrics::Metrics};
+
+/// An email, as handed over to an `EmailSender`.
+pub struct Email<'a> {
+    pub sender: &'a SubscriberEmail,
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+impl Email<'_> {
+    /// The `List-Unsubscribe` and `List-Unsubscribe-Post` headers enabling
+    /// one-click unsubscribe, as described in RFC 8058. Empty if the email
+    /// has no unsubscribe link.
+    pub fn list_unsubscribe_headers(&self) -> Vec<(&'static str, String)> {
+        match self.unsubscribe_url {
+            Some(url) => vec![
+                ("List-Unsubscribe", format!("<{}>", url)),
+                (
+                    "List-Unsubscribe-Post",
+                    "List-Unsubscribe=One-Click".to_string(),
+                ),
+            ],
+            None => vec![],
+        }
+    }
+
+    /// The email as a MIME message, with both the html and the text body.
+    fn to_message(&self) -> Result<Message, anyhow::Error> {
+        let mut builder = Message::builder()
+            .from(self.sender.as_ref().parse().context("Invalid sender.")?)
+            .to(self
+                .recipient
+                .as_ref()
+                .parse()
+                .context("Invalid recipient.")?)
+            .subject(self.subject);
+        for (name, value) in self.list_unsubscribe_headers() {
+            builder = builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii_str(name),
+                value,
+            ));
+        }
+        builder
+            .multipart(MultiPart::alternative_plain_html(
+                self.text_content.to_string(),
+                self.html_content.to_string(),
+            ))
+            .context("Failed to build the email message.")
+    }
+}
+
+/// A way of delivering emails: a provider's API, an SMTP relay, ...
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Identifies the backend in metrics, e.g. `postmark`.
+    fn provider(&self) -> &'static str;
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError>;
+
+    /// Check that the backend is reachable and accepts our credentials,
+    /// without sending anything. Backends with nothing to check are always
+    /// available.
+    async fn probe(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+/// Sends emails from our newsletter address through the configured backend.
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    backend: Box<dyn EmailSender>,
+    retry_policy: RetryPolicy,
+    metrics: Option<Metrics>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, backend: impl EmailSender + 'static) -> Self {
+        Self {
+            sender,
+            backend: Box::new(backend),
+            retry_policy: RetryPolicy::default(),
+            metrics: None,
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Count the emails we send, and those we fail to send, in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Make a single attempt at sending an email, leaving retries to the
+    /// caller.
+    ///
+    /// When `unsubscribe_url` is set the message carries the RFC 8058
+    /// one-click unsubscribe headers.
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_url: Option<&str>,
+    ) -> Result<(), SendEmailError> {
+        let email = Email {
+            sender: &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_url,
+        };
+        let outcome = self.backend.send(&email).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_email(self.backend.provider(), outcome.is_ok());
+        }
+        outcome
+    }
+
+    /// Check that emails can be handed over to the backend.
+    pub async fn probe(&self) -> Result<(), anyhow::Error> {
+        self.backend.probe().await
+    }
+}
@@ -0,0 +1,452 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+
+use super::{Email, EmailSender, SendEmailError, error::check_response};
+use crate::telemetry::trace_context_headers;
+
+/// Sends emails through Postmark's `/email` API.
+pub struct PostmarkEmailSender {
+    http_client: Client,
+    base_url: String,
+    authorization_token: SecretString,
+}
+
+impl PostmarkEmailSender {
+    pub fn new(base_url: String, authorization_token: SecretString, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkEmailSender {
+    fn provider(&self) -> &'static str {
+        "postmark"
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        let url = format!("{}/email", self.base_url);
+        let list_unsubscribe_headers = email.list_unsubscribe_headers();
+        let request_body = SendEmailRequest {
+            from: email.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: email.subject,
+            html_body: email.html_content,
+            text_body: email.text_content,
+            message_stream: "outbound",
+            headers: list_unsubscribe_headers
+                .iter()
+                .map(|(name, value)| EmailHeader { name, value })
+                .collect(),
+        };
+
+        let outcome = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .headers(trace_context_headers())
+            .send()
+            .await;
+        check_response(outcome)
+    }
+
+    /// Fetch the server the token belongs to, which fails if the token was
+    /// revoked.
+    async fn probe(&self) -> Result<(), anyhow::Error> {
+        self.http_client
+            .get(format!("{}/server", self.base_url))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .header("Accept", "application/json")
+            .headers(trace_context_headers())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    message_stream: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, PostmarkEmailSender, SendEmailError},
+    };
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        Fake, Faker,
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+    };
+    use secrecy::SecretString;
+    use wiremock::{
+        Match, Mock, MockServer, Request, ResponseTemplate,
+        matchers::{any, header, header_exists, method, path},
+    };
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let authorization_token = SecretString::from(Faker.fake::<String>());
+        EmailClient::new(
+            email(),
+            PostmarkEmailSender::new(base_url, authorization_token, Duration::from_millis(200)),
+        )
+    }
+
+    struct SendEmailBodyMatcher;
+    impl Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+    }
+
+    struct ListUnsubscribeHeadersMatcher;
+    impl Match for ListUnsubscribeHeadersMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body["Headers"]
+                    == serde_json::json!([
+                        {
+                            "Name": "List-Unsubscribe",
+                            "Value": "<https://example.com/unsubscribe>",
+                        },
+                        {
+                            "Name": "List-Unsubscribe-Post",
+                            "Value": "List-Unsubscribe=One-Click",
+                        },
+                    ])
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_sets_list_unsubscribe_headers_when_given_a_link() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(ListUnsubscribeHeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                Some("https://example.com/unsubscribe"),
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+
+        // Assert
+        assert_ok!(outcome)
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_429_is_reported_as_rate_limited_with_its_retry_after() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+
+        // Assert
+        match outcome {
+            Err(SendEmailError::RateLimited { retry_after }) => {
+                assert_eq!(retry_after, Some(Duration::from_secs(30)))
+            }
+            other => panic!("Expected a rate limited error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_422_is_reported_as_a_permanent_failure() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Permanent(_))));
+    }
+
+    #[tokio::test]
+    async fn other_client_errors_are_reported_as_permanent_failures() {
+        for status in [400, 413] {
+            // Arrange
+            let mock_server = MockServer::start().await;
+            let email_client = email_client(mock_server.uri());
+
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+
+            // Act
+            let outcome = email_client
+                .send_email(&email(), &subject(), &content(), &content(), None)
+                .await;
+
+            // Assert
+            assert!(
+                matches!(outcome, Err(SendEmailError::Permanent(_))),
+                "A {} was not reported as a permanent failure.",
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn a_401_is_reported_as_a_credentials_failure() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), None)
+            .await;
+
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn server_errors_and_timeouts_are_reported_as_transient_failures() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..2 {
+            // Act
+            let outcome = email_client
+                .send_email(&email(), &subject(), &content(), &content(), None)
+                .await;
+
+            // Assert
+            assert!(matches!(outcome, Err(SendEmailError::Transient(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn probe_fetches_the_server_with_our_token() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/server"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.probe().await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn probe_fails_if_the_token_is_rejected() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.probe().await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use super::SendEmailError;
+
+/// How often, and how patiently, failed sends are attempted again.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    /// The longest we wait between two attempts, unless the provider asks
+    /// for more with `Retry-After`.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What becomes of a queued email after an attempt at sending it.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NextStep {
+    /// Sent, or given up on: take it off the queue.
+    Remove,
+    Reschedule(Duration),
+    /// Try again after the delay without counting an attempt: it is our
+    /// configuration that failed, not the email.
+    Postpone(Duration),
+}
+
+impl RetryPolicy {
+    /// Shared by the background queues, so that they all retry the same
+    /// way. `n_retries` counts the failed attempts before this one, and
+    /// `description` says what was being sent when logging a failure.
+    pub fn next_step(
+        &self,
+        outcome: Result<(), SendEmailError>,
+        n_retries: u32,
+        description: &str,
+    ) -> NextStep {
+        let Err(e) = outcome else {
+            return NextStep::Remove;
+        };
+        if let SendEmailError::Unauthorized(_) = e {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send {}: the email provider refused our credentials. \
+                Postponing it by {:?} until the configuration is fixed.",
+                description,
+                self.max_backoff
+            );
+            return NextStep::Postpone(self.max_backoff);
+        }
+        let n_failed_attempts = n_retries + 1;
+        match self.next_delay(n_failed_attempts, &e) {
+            Some(delay) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send {}. Rescheduling it in {:?}.",
+                    description,
+                    delay
+                );
+                NextStep::Reschedule(delay)
+            }
+            None => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send {}. Giving up after {} attempts.",
+                    description,
+                    n_failed_attempts
+                );
+                NextStep::Remove
+            }
+        }
+    }
+
+    /// How long to wait before trying again after `n_failed_attempts`
+    /// failures, the last of which was `error`. `None` means giving up.
+    ///
+    /// The delay doubles with every failure, up to `max_backoff`, unless the
+    /// provider told us how long to wait with `Retry-After`.
+    ///
+    /// We never give up on emails refused because of our credentials: they
+    /// wait, `max_backoff` at a time, for the configuration to be fixed.
+    /// `next_step` does not count those attempts.
+    pub fn next_delay(&self, n_failed_attempts: u32, error: &SendEmailError) -> Option<Duration> {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(n_failed_attempts.saturating_sub(1)))
+            .min(self.max_backoff);
+        match error {
+            SendEmailError::Unauthorized(_) => Some(self.max_backoff),
+            _ if n_failed_attempts >= self.max_attempts => None,
+            SendEmailError::Permanent(_) => None,
+            SendEmailError::RateLimited {
+                retry_after: Some(retry_after),
+            } => Some(*retry_after),
+            SendEmailError::RateLimited { retry_after: None } | SendEmailError::Transient(_) => {
+                Some(backoff)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_none, assert_some_eq};
+
+    use super::{NextStep, RetryPolicy};
+    use crate::email_client::SendEmailError;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    fn transient() -> SendEmailError {
+        SendEmailError::Transient(anyhow::anyhow!("Connection reset"))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = policy();
+        assert_some_eq!(policy.next_delay(1, &transient()), Duration::from_secs(1));
+        assert_some_eq!(policy.next_delay(2, &transient()), Duration::from_secs(2));
+        assert_some_eq!(policy.next_delay(3, &transient()), Duration::from_secs(4));
+        assert_some_eq!(policy.next_delay(4, &transient()), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        assert_none!(policy().next_delay(5, &transient()));
+    }
+
+    #[test]
+    fn permanent_failures_are_not_retried() {
+        let error = SendEmailError::Permanent(anyhow::anyhow!("Invalid recipient"));
+        assert_none!(policy().next_delay(1, &error));
+    }
+
+    #[test]
+    fn credential_failures_are_retried_past_max_attempts() {
+        let error = SendEmailError::Unauthorized(anyhow::anyhow!("Invalid token"));
+        assert_some_eq!(policy().next_delay(5, &error), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn queued_emails_are_removed_once_sent_or_given_up_on() {
+        let policy = policy();
+        assert_eq!(
+            policy.next_step(Ok(()), 0, "a test email"),
+            NextStep::Remove
+        );
+        assert_eq!(
+            policy.next_step(Err(transient()), 1, "a test email"),
+            NextStep::Reschedule(Duration::from_secs(2))
+        );
+        assert_eq!(
+            policy.next_step(Err(transient()), 4, "a test email"),
+            NextStep::Remove
+        );
+    }
+
+    #[test]
+    fn queued_emails_refused_for_our_credentials_are_postponed_without_counting_an_attempt() {
+        let error = SendEmailError::Unauthorized(anyhow::anyhow!("Invalid token"));
+        assert_eq!(
+            policy().next_step(Err(error), 4, "a test email"),
+            NextStep::Postpone(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn rate_limits_honour_retry_after() {
+        let error = SendEmailError::RateLimited {
+            retry_after: Some(Duration::from_secs(30)),
+        };
+        assert_some_eq!(policy().next_delay(1, &error), Duration::from_secs(30));
+    }
+}
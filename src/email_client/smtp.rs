@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+    transport::smtp::authentication::Credentials,
+};
+use secrecy::{ExposeSecret, SecretString};
+
+use super::{Email, EmailSender, SendEmailError};
+
+/// Sends emails through an SMTP relay.
+pub struct SmtpEmailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpEmailSender {
+    /// Without `require_tls` the connection is in plain text, which is only
+    /// fine for a relay running on the same host, e.g. Mailpit in local dev.
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, SecretString)>,
+        require_tls: bool,
+        timeout: Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let mut builder = if require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        }
+        .port(port)
+        .timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_string(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpEmailSender {
+    fn provider(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+        let message = email.to_message().map_err(SendEmailError::Permanent)?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            // 535: authentication credentials invalid
+            Err(e) if e.status().is_some_and(|code| u16::from(code) == 535) => {
+                Err(SendEmailError::Unauthorized(e.into()))
+            }
+            // 5xx replies, e.g. an unknown mailbox
+            Err(e) if e.is_permanent() => Err(SendEmailError::Permanent(e.into())),
+            Err(e) => Err(SendEmailError::Transient(e.into())),
+        }
+    }
+
+    /// Connect and greet the relay, authenticating if we have credentials.
+    async fn probe(&self) -> Result<(), anyhow::Error> {
+        if self.transport.test_connection().await? {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "The SMTP relay did not accept our greeting."
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{domain::SubscriberEmail, email_client::Email};
+
+    #[test]
+    fn messages_carry_both_bodies_and_the_list_unsubscribe_headers() {
+        let sender = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+        let email = Email {
+            sender: &sender,
+            recipient: &recipient,
+            subject: "Welcome!",
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            unsubscribe_url: Some("https://example.com/unsubscribe"),
+        };
+
+        let message = String::from_utf8(email.to_message().unwrap().formatted()).unwrap();
+
+        assert!(message.contains("From: newsletter@example.com"));
+        assert!(message.contains("To: ursula@example.com"));
+        assert!(message.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(message.contains("<p>Hello</p>"));
+        assert!(message.contains("Content-Type: text/plain"));
+    }
+}
@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, field::display};
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberEmail,
+    email_client::{EmailClient, NextStep},
+    issue_delivery_worker::ExecutionOutcome,
+    telemetry::{current_traceparent, resume_trace},
+};
+
+/// An email waiting in `email_outbox`.
+pub struct OutboxEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+/// Add an email to the outbox as part of `transaction`: it is sent if, and
+/// only if, the transaction commits.
+#[tracing::instrument(name = "Adding an email to the outbox", skip_all)]
+pub async fn enqueue_email(
+    transaction: &mut PgConnection,
+    email: OutboxEmail<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (
+            id, recipient, subject, html_content, text_content, traceparent
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        email.recipient.as_ref(),
+        email.subject,
+        email.html_content,
+        email.text_content,
+        current_traceparent(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to add an email to the outbox.")?;
+    Ok(())
+}
+
+/// Keep sending the emails waiting in `email_outbox` until `shutdown` is
+/// cancelled. The email at hand, if any, is finished first.
+///
+/// Like the issue delivery worker, emails are claimed with
+/// `FOR UPDATE SKIP LOCKED` so that several instances can share the outbox.
+pub async fn run_dispatcher_until_stopped(
+    pool: PgPool,
+    email_client: EmailClient,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        // Somebody is waiting for their confirmation link: poll often.
+        let pause = match try_dispatch_email(&pool, &email_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) | Err(_) => Duration::from_secs(1),
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        email_id = tracing::field::Empty,
+        recipient = tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_dispatch_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, email)) = dequeue_email(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("email_id", display(email.id))
+        .record("recipient", display(&email.recipient));
+
+    match SubscriberEmail::parse(email.recipient.clone()) {
+        Ok(recipient) => {
+            // Part of the trace of the request that queued the email
+            let span = tracing::info_span!("Sending an email from the outbox");
+            if let Some(traceparent) = &email.traceparent {
+                resume_trace(&span, traceparent);
+            }
+            let outcome = email_client
+                .send_email(
+                    &recipient,
+                    &email.subject,
+                    &email.html_content,
+                    &email.text_content,
+                    None,
+                )
+                .instrument(span)
+                .await;
+            match email_client.retry_policy().next_step(
+                outcome,
+                email.n_retries as u32,
+                "an email from the outbox",
+            ) {
+                NextStep::Remove => delete_email(&mut transaction, &email).await?,
+                NextStep::Reschedule(delay) => {
+                    reschedule_email(&mut transaction, &email, delay, true).await?
+                }
+                NextStep::Postpone(delay) => {
+                    reschedule_email(&mut transaction, &email, delay, false).await?
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping an email from the outbox. Its recipient is invalid."
+            );
+            delete_email(&mut transaction, &email).await?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to dispatch an email.")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct PendingEmail {
+    id: Uuid,
+    recipient: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    n_retries: i16,
+    traceparent: Option<String>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_email(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, PendingEmail)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let email = sqlx::query_as!(
+        PendingEmail,
+        r#"
+        SELECT id, recipient, subject, html_content, text_content, n_retries, traceparent
+        FROM email_outbox
+        WHERE execute_after <= now()
+        ORDER BY execute_after
+        LIMIT 1
+        FOR UPDATE
+        SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to dequeue an email from the outbox.")?;
+    Ok(email.map(|email| (transaction, email)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_email(
+    transaction: &mut PgTransaction,
+    email: &PendingEmail,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!("DELETE FROM email_outbox WHERE id = $1", email.id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete an email from the outbox.")?;
+    Ok(())
+}
+
+/// Push the email back by `delay`, as decided by the email client's retry
+/// policy, counting a failed attempt if `count_attempt` is set.
+#[tracing::instrument(skip_all)]
+async fn reschedule_email(
+    transaction: &mut PgTransaction,
+    email: &PendingEmail,
+    delay: Duration,
+    count_attempt: bool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET
+            n_retries = n_retries + $3,
+            execute_after = now() + make_interval(secs => $2)
+        WHERE id = $1
+        "#,
+        email.id,
+        delay.as_secs_f64(),
+        i16::from(count_attempt),
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to reschedule an email from the outbox.")?;
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio_util::sync::CancellationToken;
+use tracing::{Span, field::display};
+use uuid::Uuid;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriptionStatus},
+    email_client::{EmailClient, NextStep},
+};
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Keep pulling tasks out of `issue_delivery_queue` until `shutdown` is
+/// cancelled. The task at hand, if any, is finished first.
+///
+/// Tasks are claimed with `FOR UPDATE SKIP LOCKED`, so several instances of
+/// the application can drain the same queue without sending an issue twice.
+pub async fn run_worker_until_stopped(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        let pause = match try_execute_task(&pool, &email_client, &base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => Duration::from_secs(10),
+            Err(_) => Duration::from_secs(1),
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = tracing::field::Empty,
+        subscriber_email = tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email));
+
+    let Some(unsubscribe_token) = get_unsubscribe_token(pool, &task.subscriber_email).await? else {
+        tracing::info!("Skipping a subscriber who is no longer confirmed.");
+        delete_task(&mut transaction, &task).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to complete a delivery task.")?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+
+    match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => {
+            let issue = get_issue(pool, task.newsletter_issue_id).await?;
+            let unsubscribe_link = format!(
+                "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+                base_url, unsubscribe_token
+            );
+            let html_content = format!(
+                "{}<p><a href=\"{}\">Unsubscribe</a></p>",
+                issue.html_content, unsubscribe_link
+            );
+            let text_content = format!(
+                "{}\n\nUnsubscribe: {}",
+                issue.text_content, unsubscribe_link
+            );
+            let outcome = email_client
+                .send_email(
+                    &email,
+                    &issue.title,
+                    &html_content,
+                    &text_content,
+                    Some(&unsubscribe_link),
+                )
+                .await;
+            match email_client.retry_policy().next_step(
+                outcome,
+                task.n_retries as u32,
+                "an issue to a confirmed subscriber",
+            ) {
+                NextStep::Remove => delete_task(&mut transaction, &task).await?,
+                NextStep::Reschedule(delay) => {
+                    reschedule_task(&mut transaction, &task, delay, true).await?
+                }
+                NextStep::Postpone(delay) => {
+                    reschedule_task(&mut transaction, &task, delay, false).await?
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid."
+            );
+            delete_task(&mut transaction, &task).await?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to complete a delivery task.")?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        ORDER BY execute_after
+        LIMIT 1
+        FOR UPDATE
+        SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to dequeue a delivery task.")?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete a completed delivery task.")?;
+    Ok(())
+}
+
+/// Push the task back by `delay`, as decided by the email client's retry
+/// policy, counting a failed attempt if `count_attempt` is set.
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+    count_attempt: bool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + $4,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        delay.as_secs_f64(),
+        i16::from(count_attempt),
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to reschedule a delivery task.")?;
+    Ok(())
+}
+
+/// Returns `None` if the subscriber left the list, or was removed from it,
+/// after the delivery was enqueued.
+#[tracing::instrument(skip_all)]
+async fn get_unsubscribe_token(
+    pool: &PgPool,
+    subscriber_email: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT unsubscribe_token
+        FROM subscriptions
+        WHERE email = $1 AND status = $2
+        "#,
+        subscriber_email,
+        SubscriptionStatus::Confirmed.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscriber of a delivery task.")?;
+    Ok(row.map(|r| r.unsubscribe_token))
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to fetch the newsletter issue to deliver.")?;
+    Ok(issue)
+}
@@ -1,6 +1,18 @@
+pub mod authentication;
+pub mod captcha;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_dispatcher;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod metrics;
+pub mod problem_details;
+pub mod rate_limit;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
+pub mod subscription_cleanup_worker;
 pub mod telemetry;
+pub mod utils;
@@ -4,11 +4,21 @@ use zero2prod::telemetry;
 
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
-    let subscriber = telemetry::get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+    let configuration = get_configuration().expect("Failed to read configuration");
+
+    let tracer = configuration
+        .telemetry
+        .otlp_endpoint
+        .is_some()
+        .then(|| telemetry::get_tracer("zero2prod".into(), &configuration.telemetry))
+        .transpose()
+        .expect("Failed to build the OpenTelemetry tracer");
+    let subscriber =
+        telemetry::get_subscriber("zero2prod".into(), "info".into(), std::io::stdout, tracer);
     telemetry::init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("Failed to read configuration");
     let server = Application::build(configuration).await?;
-    server.run_until_stopped().await?;
-    Ok(())
+    let outcome = server.run_until_stopped().await;
+    telemetry::shutdown_tracer_provider();
+    outcome
 }
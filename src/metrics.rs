@@ -0,0 +1,227 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::Data,
+};
+use anyhow::Context;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use sqlx::PgPool;
+
+/// Our Prometheus metrics. Cheap to clone, clones update the same metrics.
+///
+/// Each `Application` gets its own registry rather than using the process
+/// wide default one, so that applications running side by side, e.g. in
+/// tests, do not mix up their numbers.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests_total: IntCounterVec,
+    http_request_duration_seconds: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+    subscriptions_created_total: IntCounter,
+    subscriptions_confirmed_total: IntCounter,
+    emails_sent_total: IntCounterVec,
+    emails_failed_total: IntCounterVec,
+    issue_delivery_queue_depth: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests.",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Connections currently open in the Postgres pool.",
+            ),
+            &["state"],
+        )
+        .unwrap();
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum number of connections in the Postgres pool.",
+        )
+        .unwrap();
+        let subscriptions_created_total = IntCounter::new(
+            "subscriptions_created_total",
+            "New subscribers waiting for confirmation.",
+        )
+        .unwrap();
+        let subscriptions_confirmed_total = IntCounter::new(
+            "subscriptions_confirmed_total",
+            "Subscribers who confirmed their subscription.",
+        )
+        .unwrap();
+        let emails_sent_total = IntCounterVec::new(
+            Opts::new("emails_sent_total", "Emails handed over to the provider."),
+            &["provider"],
+        )
+        .unwrap();
+        let emails_failed_total = IntCounterVec::new(
+            Opts::new(
+                "emails_failed_total",
+                "Failed attempts at sending an email, retries included.",
+            ),
+            &["provider"],
+        )
+        .unwrap();
+        let issue_delivery_queue_depth = IntGauge::new(
+            "issue_delivery_queue_depth",
+            "Newsletter deliveries waiting in the queue.",
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(http_requests_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(http_request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_max_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(subscriptions_created_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(subscriptions_confirmed_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(emails_sent_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(emails_failed_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(issue_delivery_queue_depth.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            db_pool_connections,
+            db_pool_max_connections,
+            subscriptions_created_total,
+            subscriptions_confirmed_total,
+            emails_sent_total,
+            emails_failed_total,
+            issue_delivery_queue_depth,
+        }
+    }
+
+    pub fn record_subscription_created(&self) {
+        self.subscriptions_created_total.inc();
+    }
+
+    pub fn record_subscription_confirmed(&self) {
+        self.subscriptions_confirmed_total.inc();
+    }
+
+    pub fn record_email(&self, provider: &str, succeeded: bool) {
+        let counter = if succeeded {
+            &self.emails_sent_total
+        } else {
+            &self.emails_failed_total
+        };
+        counter.with_label_values(&[provider]).inc();
+    }
+
+    /// Refresh the gauges that are read from Postgres, then encode every
+    /// metric in the Prometheus text format.
+    pub async fn render(&self, pool: &PgPool) -> Result<String, anyhow::Error> {
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(pool.size() as i64 - idle);
+        self.db_pool_max_connections
+            .set(pool.options().get_max_connections() as i64);
+        // A broken database should not hide the other metrics
+        match queue_depth(pool).await {
+            Ok(depth) => self.issue_delivery_queue_depth.set(depth),
+            Err(e) => tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to measure the issue delivery queue depth."
+            ),
+        }
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("Failed to encode metrics.")?;
+        String::from_utf8(buffer).context("Metrics are not valid UTF-8.")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn queue_depth(pool: &PgPool) -> Result<i64, anyhow::Error> {
+    let depth = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(pool)
+        .await
+        .context("Failed to count the pending deliveries.")?;
+    Ok(depth)
+}
+
+/// Count requests and time them, labelled by route pattern rather than path
+/// so that ids and tokens do not blow up the number of series.
+pub async fn record_request_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req
+        .app_data::<Data<Metrics>>()
+        .expect("Metrics are not registered as application data.")
+        .clone();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let start = Instant::now();
+
+    let outcome = next.call(req).await;
+
+    let status = match &outcome {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    metrics
+        .http_requests_total
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .inc();
+    metrics
+        .http_request_duration_seconds
+        .with_label_values(&[method.as_str(), route.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    outcome
+}
@@ -0,0 +1,167 @@
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    error::InternalError,
+    http::{
+        StatusCode,
+        header::{Accept, ContentType, Header},
+    },
+};
+
+use crate::{domain::InvalidField, utils::has_json_body};
+
+/// An RFC 7807 problem document, sent instead of our plain text errors to
+/// clients that prefer JSON.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ProblemDetails {
+    /// We only use `about:blank`: the status code says it all.
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    problem_type: &'static str,
+    #[schema(example = "Bad Request")]
+    title: &'static str,
+    #[schema(example = 400)]
+    status: u16,
+    detail: String,
+    /// The fields that failed validation, if any.
+    #[serde(rename = "invalid-params", skip_serializing_if = "Vec::is_empty")]
+    invalid_params: Vec<InvalidParam>,
+}
+
+/// A field that failed validation, named as in the request.
+#[derive(serde::Serialize, utoipa::ToSchema, Clone, Debug)]
+pub struct InvalidParam {
+    #[schema(example = "email")]
+    pub name: &'static str,
+    #[schema(example = "The email address is not valid.")]
+    pub reason: String,
+    /// Stable across wordings of `reason`, to show a translated message.
+    #[schema(example = "invalid_syntax")]
+    pub code: &'static str,
+}
+
+impl From<&InvalidField> for InvalidParam {
+    fn from(value: &InvalidField) -> Self {
+        Self {
+            name: value.field(),
+            reason: value.to_string(),
+            code: value.code(),
+        }
+    }
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            invalid_params: Vec::new(),
+        }
+    }
+
+    pub fn invalid_params(detail: impl Into<String>, invalid_params: Vec<InvalidParam>) -> Self {
+        Self {
+            invalid_params,
+            ..Self::new(StatusCode::BAD_REQUEST, detail)
+        }
+    }
+
+    pub fn response(&self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_REQUEST);
+        HttpResponse::build(status)
+            .content_type(ContentType(
+                "application/problem+json".parse::<mime::Mime>().unwrap(),
+            ))
+            .json(self)
+    }
+}
+
+/// Error handler for the body extractors of endpoints serving HTML forms
+/// and scripts alike: clients that prefer JSON get a problem document,
+/// the others actix's plain text error.
+pub fn problem_input_error<E>(e: E, req: &HttpRequest) -> actix_web::Error
+where
+    E: ResponseError + 'static,
+{
+    match ErrorFormat::preferred_by(req) {
+        ErrorFormat::ProblemJson => {
+            let response = ProblemDetails::new(e.status_code(), e.to_string()).response();
+            InternalError::from_response(e, response).into()
+        }
+        ErrorFormat::PlainText => e.into(),
+    }
+}
+
+/// How a client wants to read our errors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    PlainText,
+    ProblemJson,
+}
+
+impl ErrorFormat {
+    /// JSON if it is the client's first choice in `Accept`. Clients without a
+    /// preference get errors in the format they sent their request in.
+    pub fn preferred_by(req: &HttpRequest) -> Self {
+        let preference = Accept::parse(req)
+            .ok()
+            .and_then(|accept| accept.ranked().into_iter().next())
+            .filter(|mime| mime.type_() != mime::STAR);
+        let prefers_json = match preference {
+            Some(mime) => mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON),
+            None => has_json_body(req),
+        };
+        if prefers_json {
+            Self::ProblemJson
+        } else {
+            Self::PlainText
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::ErrorFormat;
+
+    #[test]
+    fn json_is_used_when_it_is_the_first_choice() {
+        let req = TestRequest::default()
+            .insert_header(("Accept", "text/html;q=0.5, application/json"))
+            .to_http_request();
+        assert_eq!(ErrorFormat::preferred_by(&req), ErrorFormat::ProblemJson);
+    }
+
+    #[test]
+    fn plain_text_is_used_when_html_comes_first() {
+        let req = TestRequest::default()
+            .insert_header(("Accept", "text/html, application/json;q=0.9"))
+            .insert_header(("Content-Type", "application/json"))
+            .to_http_request();
+        assert_eq!(ErrorFormat::preferred_by(&req), ErrorFormat::PlainText);
+    }
+
+    #[test]
+    fn clients_without_a_preference_get_the_format_they_sent() {
+        for accept in [None, Some("*/*")] {
+            let mut json =
+                TestRequest::default().insert_header(("Content-Type", "application/json"));
+            let mut form = TestRequest::default()
+                .insert_header(("Content-Type", "application/x-www-form-urlencoded"));
+            if let Some(accept) = accept {
+                json = json.insert_header(("Accept", accept));
+                form = form.insert_header(("Accept", accept));
+            }
+            assert_eq!(
+                ErrorFormat::preferred_by(&json.to_http_request()),
+                ErrorFormat::ProblemJson
+            );
+            assert_eq!(
+                ErrorFormat::preferred_by(&form.to_http_request()),
+                ErrorFormat::PlainText
+            );
+        }
+    }
+}
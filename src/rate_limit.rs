@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    HttpMessage, HttpResponse,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::{HeaderName, RETRY_AFTER},
+    middleware::Next,
+    web::Data,
+};
+
+use crate::{configuration::RateLimitSettings, domain::SubscriberEmail};
+
+/// Token bucket rate limiter: each key may spend `limit` requests in a burst,
+/// and gets them back at a steady pace over `period`.
+pub struct RateLimiter<K> {
+    limit: u32,
+    period: Duration,
+    state: Mutex<State<K>>,
+}
+
+struct State<K> {
+    buckets: HashMap<K, Bucket>,
+    last_pruned: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(limit: u32, period: Duration) -> Self {
+        Self {
+            limit,
+            period,
+            state: Mutex::new(State {
+                buckets: HashMap::new(),
+                last_pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Spend one request from `key`'s allowance, or learn how long to wait
+    /// before the next one is allowed.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        if self.limit == 0 {
+            return Err(self.period);
+        }
+        let capacity = self.limit as f64;
+        let refill_per_second = capacity / self.period.as_secs_f64();
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated_at);
+            (bucket.tokens + elapsed.as_secs_f64() * refill_per_second).min(capacity)
+        };
+
+        let mut state = self.state.lock().unwrap();
+        // Forget keys that are back to a full bucket, they would start from
+        // one anyway. Keeps memory bounded by the traffic of a single period.
+        if now.saturating_duration_since(state.last_pruned) >= self.period {
+            state.buckets.retain(|_, bucket| refill(bucket) < capacity);
+            state.last_pruned = now;
+        }
+
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / refill_per_second,
+            ))
+        }
+    }
+}
+
+/// The client's IP, as seen by our reverse proxy when it is trusted.
+///
+/// Stored in the request extensions by `rate_limit_subscriptions`, handlers
+/// can get it back with `web::ReqData<ClientIp>`.
+#[derive(Copy, Clone, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+/// The limits shared by the public endpoints that send emails.
+///
+/// Limits by IP are enforced by `rate_limit_subscriptions`, limits by email
+/// address by the handlers themselves, see `check_email`.
+pub struct SubscriptionRateLimits {
+    by_ip: RateLimiter<IpAddr>,
+    by_email: RateLimiter<String>,
+    trusted_proxy_headers: Vec<HeaderName>,
+}
+
+impl SubscriptionRateLimits {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        Self {
+            by_ip: RateLimiter::new(settings.requests_per_ip, settings.period()),
+            by_email: RateLimiter::new(settings.requests_per_email, settings.period()),
+            trusted_proxy_headers: settings
+                .trusted_proxy_headers
+                .iter()
+                .map(|name| {
+                    HeaderName::try_from(name.as_str())
+                        .unwrap_or_else(|_| panic!("{:?} is not a valid header name.", name))
+                })
+                .collect(),
+        }
+    }
+
+    /// Spend one request from the allowance of `email`, or learn how long to
+    /// wait before the next one is allowed. Only call it for requests that
+    /// got past our bot checks, or bots could use up the allowance of any
+    /// address and keep its owner from signing up.
+    pub fn check_email(&self, email: &SubscriberEmail) -> Result<(), Duration> {
+        self.by_email.check(email.as_ref().to_lowercase())
+    }
+
+    /// The first trusted proxy header carrying an IP wins, we fall back to
+    /// the peer address when running without a proxy in front.
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        self.trusted_proxy_headers
+            .iter()
+            .filter_map(|name| req.headers().get(name)?.to_str().ok())
+            // Proxies append to `X-Forwarded-For`: the last entry is the
+            // address our proxy saw, anything before it the client can forge.
+            .filter_map(|value| value.rsplit(',').next()?.trim().parse().ok())
+            .next()
+            .or_else(|| req.peer_addr().map(|address| address.ip()))
+    }
+}
+
+/// Reject with a 429 clients that call us too often from the same IP, as
+/// each call sends an email.
+pub async fn rate_limit_subscriptions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let limits = req
+        .app_data::<Data<SubscriptionRateLimits>>()
+        .expect("The rate limits are not registered as application data.")
+        .clone();
+
+    if let Some(ip) = limits.client_ip(&req) {
+        req.extensions_mut().insert(ClientIp(ip));
+        limits
+            .by_ip
+            .check(ip)
+            .map_err(|wait| too_many_requests(wait, format!("Too many requests from {}.", ip)))?;
+    }
+
+    next.call(req).await
+}
+
+fn too_many_requests(wait: Duration, message: String) -> actix_web::Error {
+    InternalError::from_response(anyhow::anyhow!(message), too_many_requests_response(wait)).into()
+}
+
+/// A 429 telling the client how long to wait before trying again.
+pub fn too_many_requests_response(wait: Duration) -> HttpResponse {
+    // Round up, a client retrying right on time must get through
+    let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, seconds.to_string()))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use claims::{assert_err, assert_ok};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn requests_within_the_limit_are_allowed() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_ok!(limiter.check_at("key", now));
+        }
+    }
+
+    #[test]
+    fn requests_over_the_limit_are_told_when_to_retry() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at("key", now).unwrap();
+        }
+
+        let wait = assert_err!(limiter.check_at("key", now));
+        assert_eq!(wait, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn allowance_is_given_back_over_time() {
+        let limiter = RateLimiter::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.check_at("key", now).unwrap();
+        }
+
+        assert_ok!(limiter.check_at("key", now + Duration::from_secs(20)));
+        assert_err!(limiter.check_at("key", now + Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn keys_are_limited_independently() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+        limiter.check_at("a", now).unwrap();
+
+        assert_err!(limiter.check_at("a", now));
+        assert_ok!(limiter.check_at("b", now));
+    }
+
+    #[test]
+    fn a_zero_limit_rejects_everything() {
+        let limiter = RateLimiter::new(0, Duration::from_secs(60));
+        assert_err!(limiter.check_at("key", Instant::now()));
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt::Write;
+
+use actix_web::{
+    HttpResponse,
+    http::header::{CACHE_CONTROL, ContentType, HeaderValue},
+    web::{self, Data},
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication,
+    utils::{e500, see_other},
+};
+
+pub async fn api_keys_form(
+    flash_messages: IncomingFlashMessages,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    api_keys_page(&msg_html, &pg_pool).await
+}
+
+/// The list of API keys and the form to create one, below `msg_html`.
+async fn api_keys_page(msg_html: &str, pg_pool: &PgPool) -> Result<HttpResponse, actix_web::Error> {
+    let api_keys = authentication::list_api_keys(pg_pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for api_key in &api_keys {
+        let format_date = |date: Option<chrono::DateTime<chrono::Utc>>| {
+            date.map(|d| d.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default()
+        };
+        let action_html = if api_key.revoked_at.is_none() {
+            format!(
+                r#"<form action="/admin/api_keys/{}/revoke" method="post"><button type="submit">Revoke</button></form>"#,
+                api_key.api_key_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&api_key.name),
+            api_key.created_at.format("%Y-%m-%d %H:%M"),
+            format_date(api_key.last_used_at),
+            format_date(api_key.revoked_at),
+            action_html,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API keys</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr><th>Name</th><th>Created at</th><th>Last used at</th><th>Revoked at</th><th></th></tr>
+        {rows_html}
+    </table>
+    <form action="/admin/api_keys" method="post">
+        <label>Name
+            <input type="text" placeholder="What the key is for" name="name">
+        </label>
+        <button type="submit">Create API key</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ApiKeyFormData {
+    name: String,
+}
+
+pub async fn create_api_key(
+    form: web::Form<ApiKeyFormData>,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Give the API key a name.").send();
+        return Ok(see_other("/admin/api_keys"));
+    }
+
+    let (_, key) = authentication::create_api_key(name, &pg_pool)
+        .await
+        .map_err(e500)?;
+    // Shown in this response only: a flash message would put it in a cookie
+    let msg_html = format!(
+        "<p><i>Your new API key is {} - copy it now, it will not be shown again.</i></p>",
+        htmlescape::encode_minimal(key.expose_secret())
+    );
+    let mut response = api_keys_page(&msg_html, &pg_pool).await?;
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok(response)
+}
+
+pub async fn revoke_api_key(
+    api_key_id: web::Path<Uuid>,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    authentication::revoke_api_key(*api_key_id, &pg_pool)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("The API key has been revoked.").send();
+    Ok(see_other("/admin/api_keys"))
+}
@@ -0,0 +1,105 @@
+use std::fmt::Write;
+
+use actix_web::{
+    HttpResponse,
+    http::header::ContentType,
+    web::{self, Data},
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{authentication::UserId, domain::SubscriptionStatus, utils::e500};
+
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = get_username(**user_id, &pg_pool).await.map_err(e500)?;
+    let subscribers = get_subscribers(&pg_pool).await.map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&subscriber.email),
+            htmlescape::encode_minimal(&subscriber.name),
+            subscriber.status,
+            subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {}!</p>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/api_keys">Manage API keys</a></li>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+    <p>Subscribers ({}):</p>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+        {rows_html}
+    </table>
+</body>
+</html>"#,
+            htmlescape::encode_minimal(&username),
+            subscribers.len(),
+        )))
+}
+
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve a username.")?;
+    Ok(row.username)
+}
+
+struct SubscriberRow {
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List subscribers", skip(pool))]
+async fn get_subscribers(pool: &PgPool) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT email, name, status AS "status: SubscriptionStatus", subscribed_at
+        FROM subscriptions
+        ORDER BY subscribed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to list subscribers.")?;
+    Ok(rows)
+}
@@ -0,0 +1,9 @@
+mod api_keys;
+mod dashboard;
+mod logout;
+mod password;
+
+pub use api_keys::{api_keys_form, create_api_key, revoke_api_key};
+pub use dashboard::admin_dashboard;
+pub use logout::log_out;
+pub use password::{change_password, change_password_form};
@@ -0,0 +1,69 @@
+mod subscribers;
+
+use actix_web::{HttpRequest, HttpResponse, ResponseError, error::InternalError, http::StatusCode};
+
+use super::error_chain_fmt;
+use crate::{
+    domain::ValidationErrors,
+    problem_details::{InvalidParam, ProblemDetails},
+};
+
+pub use subscribers::*;
+
+/// The errors of the JSON API, answered with RFC 7807 problem documents.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    InvalidFields(ValidationErrors),
+    #[error("There is no subscriber with this id.")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidInput(_) | Self::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::InvalidFields(errors) => ProblemDetails::invalid_params(
+                "The subscriber has invalid fields.",
+                errors.fields().iter().map(InvalidParam::from).collect(),
+            ),
+            // Internal details stay in the logs
+            Self::UnexpectedError(_) => {
+                ProblemDetails::new(self.status_code(), "Something went wrong on our side.")
+            }
+            e => ProblemDetails::new(self.status_code(), e.to_string()),
+        }
+        .response()
+    }
+}
+
+/// Error handler for the JSON, query and path extractors of the API, so
+/// that malformed requests get a problem document like any other invalid
+/// input.
+pub fn api_input_error<E>(e: E, _req: &HttpRequest) -> actix_web::Error
+where
+    E: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    let response = ApiError::InvalidInput(e.to_string()).error_response();
+    InternalError::from_response(e, response).into()
+}
@@ -0,0 +1,352 @@
+use actix_web::{
+    HttpResponse,
+    http::header::LOCATION,
+    web::{self, Data},
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use super::ApiError;
+use crate::{
+    configuration::SubscriptionSettings,
+    domain::{InvalidField, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    metrics::Metrics,
+    routes::{enqueue_confirmation_email, generate_subscription_token, issue_confirmation_token},
+    startup::ApplicationBaseUrl,
+};
+
+/// A subscriber, as the API shows it.
+#[derive(serde::Serialize)]
+pub struct SubscriberDetails {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+}
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ListParameters {
+    status: Option<SubscriptionStatus>,
+    #[serde(default = "first_page")]
+    page: u32,
+    #[serde(default = "default_page_size")]
+    per_page: u32,
+}
+
+fn first_page() -> u32 {
+    1
+}
+
+fn default_page_size() -> u32 {
+    DEFAULT_PAGE_SIZE
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberPage {
+    subscribers: Vec<SubscriberDetails>,
+    page: u32,
+    per_page: u32,
+    /// Subscribers matching the filter, across all pages.
+    total: i64,
+}
+
+/// Subscribers from the most recent, `per_page` at a time, optionally only
+/// those with the given `status`.
+#[tracing::instrument(name = "Listing subscribers through the API", skip(pg_pool))]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let ListParameters {
+        status,
+        page,
+        per_page,
+    } = parameters.into_inner();
+    if page == 0 {
+        return Err(ApiError::InvalidInput("Pages start at 1.".into()));
+    }
+    if !(1..=MAX_PAGE_SIZE).contains(&per_page) {
+        return Err(ApiError::InvalidInput(format!(
+            "per_page must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let status = status.map(|s| s.as_str());
+    let subscribers = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT id, email, name, status AS "status: SubscriptionStatus", subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+        status,
+        i64::from(per_page),
+        i64::from(page - 1) * i64::from(per_page),
+    )
+    .fetch_all(pg_pool.get_ref())
+    .await
+    .context("Failed to perform a query to list subscribers.")?;
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        "#,
+        status,
+    )
+    .fetch_one(pg_pool.get_ref())
+    .await
+    .context("Failed to count subscribers.")?;
+
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        page,
+        per_page,
+        total,
+    }))
+}
+
+#[tracing::instrument(name = "Fetching a subscriber through the API", skip(pg_pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let mut connection = pg_pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = fetch_subscriber(&mut connection, *subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewSubscriberBody {
+    email: String,
+    name: String,
+    /// Add the subscriber as confirmed rather than sending them a
+    /// confirmation email, e.g. when they opted in through another channel.
+    #[serde(default)]
+    skip_confirmation: bool,
+}
+
+#[tracing::instrument(
+    name = "Adding a subscriber through the API",
+    skip(body, pg_pool, base_url, settings, metrics),
+    fields(subscriber_email = %body.email)
+)]
+pub async fn create_subscriber(
+    body: web::Json<NewSubscriberBody>,
+    pg_pool: Data<PgPool>,
+    base_url: Data<ApplicationBaseUrl>,
+    settings: Data<SubscriptionSettings>,
+    metrics: Data<Metrics>,
+) -> Result<HttpResponse, ApiError> {
+    let NewSubscriberBody {
+        email,
+        name,
+        skip_confirmation,
+    } = body.into_inner();
+    let NewSubscriber { email, name } =
+        NewSubscriber::parse(name, email).map_err(ApiError::InvalidFields)?;
+    let status = if skip_confirmation {
+        SubscriptionStatus::Confirmed
+    } else {
+        SubscriptionStatus::PendingConfirmation
+    };
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id, email, name, status AS "status: SubscriptionStatus", subscribed_at
+        "#,
+        Uuid::new_v4(),
+        email.as_ref(),
+        name.as_ref(),
+        Utc::now(),
+        status.as_str(),
+        generate_subscription_token(),
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to insert new subscriber in the database.")?
+    .ok_or_else(|| ApiError::Conflict("A subscriber with this email already exists.".into()))?;
+
+    if status == SubscriptionStatus::PendingConfirmation {
+        let subscription_token =
+            issue_confirmation_token(&mut transaction, subscriber.id, &settings).await?;
+        enqueue_confirmation_email(&mut transaction, &email, &base_url.0, &subscription_token)
+            .await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    if status == SubscriptionStatus::PendingConfirmation {
+        metrics.record_subscription_created();
+    }
+
+    Ok(HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/v1/subscribers/{}", subscriber.id)))
+        .json(subscriber))
+}
+
+/// The fields to change, the others are left as they are.
+#[derive(serde::Deserialize)]
+pub struct SubscriberUpdate {
+    name: Option<String>,
+    status: Option<SubscriptionStatus>,
+}
+
+/// Moving a subscriber back to pending confirmation sends them a new
+/// confirmation email, as signing up again would.
+#[tracing::instrument(
+    name = "Updating a subscriber through the API",
+    skip(body, pg_pool, base_url, settings)
+)]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberUpdate>,
+    pg_pool: Data<PgPool>,
+    base_url: Data<ApplicationBaseUrl>,
+    settings: Data<SubscriptionSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let SubscriberUpdate { name, status } = body.into_inner();
+    let name = name
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|e| ApiError::InvalidFields(InvalidField::from(e).into()))?;
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let current = fetch_subscriber(&mut transaction, *subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let new_status = match status {
+        Some(status) => current
+            .status
+            .transition_to(status)
+            .map_err(ApiError::Conflict)?,
+        None => current.status,
+    };
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        UPDATE subscriptions
+        SET name = $1, status = $2
+        WHERE id = $3
+        RETURNING id, email, name, status AS "status: SubscriptionStatus", subscribed_at
+        "#,
+        name.as_ref().map_or(current.name.as_str(), AsRef::as_ref),
+        new_status.as_str(),
+        current.id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to update a subscriber.")?;
+
+    if current.status != SubscriptionStatus::PendingConfirmation
+        && new_status == SubscriptionStatus::PendingConfirmation
+    {
+        let email = SubscriberEmail::parse(subscriber.email.clone())
+            .context("A stored subscriber email is invalid.")?;
+        let subscription_token =
+            issue_confirmation_token(&mut transaction, subscriber.id, &settings).await?;
+        enqueue_confirmation_email(&mut transaction, &email, &base_url.0, &subscription_token)
+            .await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber.")?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Forget a subscriber for good: their confirmation tokens, the emails still
+/// waiting to be sent to them and the events reported for their address go
+/// too.
+#[tracing::instrument(name = "Deleting a subscriber through the API", skip(pg_pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        *subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscriber's confirmation tokens.")?;
+    let email = sqlx::query_scalar!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING email",
+        *subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete a subscriber.")?
+    .ok_or(ApiError::NotFound)?;
+    sqlx::query!("DELETE FROM email_outbox WHERE recipient = $1", email)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the emails waiting to be sent to the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the newsletter issues waiting to be sent to the subscriber.")?;
+    sqlx::query!("DELETE FROM email_events WHERE email = $1", email)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the email events of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Locks the subscriber's row until the end of the transaction, if any.
+#[tracing::instrument(name = "Fetching a subscriber by id", skip(connection))]
+async fn fetch_subscriber(
+    connection: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT id, email, name, status AS "status: SubscriptionStatus", subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *connection)
+    .await
+    .context("Failed to perform a query to fetch a subscriber.")
+}
@@ -1,5 +1,129 @@
-use actix_web::{HttpResponse, Responder};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
 
+use actix_web::{HttpResponse, Responder, web};
+use anyhow::Context;
+use serde::Serialize;
+use sqlx::{Connection, PgPool};
+
+use crate::{configuration::HealthSettings, email_client::EmailClient};
+
+/// Whether the process is up, regardless of its dependencies.
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    status: Status,
+    checks: BTreeMap<&'static str, Check>,
+}
+
+#[derive(Serialize)]
+struct Check {
+    status: Status,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Whether the instance can serve traffic: Postgres answers and all our
+/// migrations are applied, and, if enabled in `HealthSettings`, the email
+/// provider accepts our credentials. Replies with 503 if any check fails.
+#[tracing::instrument(name = "Readiness check", skip_all)]
+pub async fn readiness(
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<HealthSettings>,
+) -> HttpResponse {
+    let timeout = settings.timeout();
+    let (database, migrations) = tokio::join!(
+        check(timeout, ping_database(&pg_pool)),
+        check(timeout, check_migrations(&pg_pool)),
+    );
+    let mut checks = BTreeMap::from([("database", database), ("migrations", migrations)]);
+    if settings.probe_email_provider {
+        checks.insert("email_provider", check(timeout, email_client.probe()).await);
+    }
+
+    let status = if checks.values().all(|c| c.status == Status::Up) {
+        Status::Up
+    } else {
+        Status::Down
+    };
+    let mut response = match status {
+        Status::Up => HttpResponse::Ok(),
+        Status::Down => HttpResponse::ServiceUnavailable(),
+    };
+    response.json(Readiness { status, checks })
+}
+
+async fn check(
+    timeout: Duration,
+    dependency: impl Future<Output = Result<(), anyhow::Error>>,
+) -> Check {
+    let start = Instant::now();
+    let outcome = match tokio::time::timeout(timeout, dependency).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(anyhow::anyhow!("Timed out after {:?}.", timeout)),
+    };
+    let latency_ms = start.elapsed().as_millis();
+    match outcome {
+        Ok(()) => Check {
+            status: Status::Up,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "A readiness check failed."
+            );
+            Check {
+                status: Status::Down,
+                latency_ms,
+                error: Some(e.to_string()),
+            }
+        }
+    }
+}
+
+async fn ping_database(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    connection.ping().await.context("Failed to ping Postgres.")
+}
+
+/// Every migration embedded in the binary must have been applied
+/// successfully, e.g. not still running on another instance.
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let applied = sqlx::query_scalar!("SELECT version FROM _sqlx_migrations WHERE success")
+        .fetch_all(pool)
+        .await
+        .context("Failed to list the applied migrations.")?;
+    let pending: Vec<_> = sqlx::migrate!()
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version.to_string())
+        .collect();
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Migrations {} are not applied.",
+            pending.join(", ")
+        ))
+    }
+}
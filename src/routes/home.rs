@@ -0,0 +1,7 @@
+use actix_web::{HttpResponse, http::header::ContentType};
+
+pub async fn home() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("home.html"))
+}
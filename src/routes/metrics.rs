@@ -0,0 +1,15 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::{metrics::Metrics, utils::e500};
+
+/// Our metrics, in the Prometheus text exposition format.
+pub async fn metrics(
+    metrics: web::Data<Metrics>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = metrics.render(&pg_pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
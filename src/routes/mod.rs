@@ -1,7 +1,25 @@
+mod admin;
+mod api;
 mod health_check;
+mod home;
+mod login;
+mod metrics;
+mod newsletters;
+mod openapi;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod webhooks;
 
+pub use admin::*;
+pub use api::*;
 pub use health_check::*;
+pub use home::*;
+pub use login::*;
+pub use metrics::*;
+pub use newsletters::*;
+pub use openapi::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use webhooks::*;
@@ -0,0 +1,160 @@
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    http::StatusCode,
+    web::{self, Data},
+};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::SubscriptionStatus,
+    idempotency::{IdempotencyKey, NextAction, save_response, try_processing},
+    routes::error_chain_fmt,
+};
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// Fallback for clients that cannot set the `Idempotency-Key` header.
+    idempotency_key: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+#[tracing::instrument(
+    name = "Publishing a newsletter issue",
+    skip(request, body, pg_pool, user_id),
+    fields(newsletter_title = %body.title, user_id = %*user_id)
+)]
+pub async fn publish_newsletter(
+    request: HttpRequest,
+    body: web::Json<BodyData>,
+    pg_pool: Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, PublishError> {
+    let BodyData {
+        title,
+        content,
+        idempotency_key,
+    } = body.0;
+    let idempotency_key = get_idempotency_key(&request, idempotency_key)?;
+    let mut transaction = match try_processing(&pg_pool, &idempotency_key, **user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content.text, &content.html)
+        .await
+        .context("Failed to store newsletter issue details.")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks.")?;
+    let response = HttpResponse::Ok().finish();
+    let response = save_response(transaction, &idempotency_key, **user_id, response)
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue.")?;
+    Ok(response)
+}
+
+/// The `Idempotency-Key` header wins over the body field when both are set.
+fn get_idempotency_key(
+    request: &HttpRequest,
+    body_key: Option<String>,
+) -> Result<IdempotencyKey, PublishError> {
+    let header_key = request
+        .headers()
+        .get("Idempotency-Key")
+        .map(|value| value.to_str().map(str::to_owned))
+        .transpose()
+        .map_err(|_| {
+            PublishError::ValidationError("The Idempotency-Key header is not valid ASCII.".into())
+        })?;
+    header_key
+        .or(body_key)
+        .ok_or_else(|| "An idempotency key is required to publish a newsletter issue.".to_string())
+        .and_then(IdempotencyKey::try_from)
+        .map_err(PublishError::ValidationError)
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Saving newsletter issue details in the database", skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut PgConnection,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content,
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(name = "Enqueueing delivery tasks", skip(transaction))]
+async fn enqueue_delivery_tasks(
+    transaction: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = $2
+        "#,
+        newsletter_issue_id,
+        SubscriptionStatus::Confirmed.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use actix_web::HttpResponse;
+use utoipa::{Modify, OpenApi, openapi};
+
+use super::{__path_confirm, __path_confirm_form, __path_subscribe};
+use crate::problem_details::{InvalidParam, ProblemDetails};
+
+/// The OpenAPI document of our public endpoints, generated from the handlers
+/// and the types they accept. `openapi.json`, at the root of the repository,
+/// is a committed copy for client authors.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zero2prod", description = "Sign up to our newsletter."),
+    paths(subscribe, confirm_form, confirm),
+    components(schemas(ProblemDetails, InvalidParam)),
+    tags((name = "subscriptions", description = "Signing up and confirming.")),
+    modifiers(&WithoutLicense)
+)]
+pub struct ApiDoc;
+
+/// utoipa fills the license in from `Cargo.toml`, where we have none: an
+/// empty one is not a valid OpenAPI license.
+struct WithoutLicense;
+
+impl Modify for WithoutLicense {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        openapi.info.license = None;
+    }
+}
+
+pub async fn openapi_spec() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -1,20 +1,31 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use actix_web::{
-    HttpResponse, Responder, ResponseError,
-    http::StatusCode,
+    HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError,
+    http::{StatusCode, header::ContentType},
     web::{self, Data},
 };
 use anyhow::Context as _;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rand::distr::{Alphanumeric, SampleString};
 use sqlx::{PgConnection, PgPool};
 use tera::Context;
+use utoipa::{
+    PartialSchema,
+    openapi::{Content, Ref, RefOr, Response, ResponseBuilder},
+};
 use uuid::Uuid;
 
 use crate::{
-    configuration::email_templates,
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    captcha::Captcha,
+    configuration::{SubscriptionSettings, email_templates},
+    domain::{InvalidField, NewSubscriber, SubscriberEmail, SubscriptionStatus, ValidationErrors},
+    email_dispatcher::{OutboxEmail, enqueue_email},
+    metrics::Metrics,
+    problem_details::{ErrorFormat, InvalidParam, ProblemDetails},
+    rate_limit::{ClientIp, SubscriptionRateLimits, too_many_requests_response},
     startup::ApplicationBaseUrl,
+    utils::JsonOrForm,
 };
 
 pub struct StoreTokenError(sqlx::Error);
@@ -38,69 +49,234 @@ impl std::fmt::Debug for StoreTokenError {
     }
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct FormData {
+    #[schema(example = "ursula_le_guin@gmail.com")]
     email: String,
+    #[schema(example = "Ursula Le Guin")]
     name: String,
+    /// Honeypot: hidden from humans by the form, bots tend to fill it in.
+    #[serde(default)]
+    website: String,
+    /// Added to the form by the CAPTCHA widget, named after the provider.
+    /// `cf-turnstile-response` is accepted too.
+    #[serde(
+        default,
+        rename = "h-captcha-response",
+        alias = "cf-turnstile-response"
+    )]
+    captcha_token: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = ValidationErrors;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { name, email })
+        NewSubscriber::parse(value.name, value.email)
     }
 }
 
+/// Sign up to the newsletter. A confirmation email is sent to new
+/// subscribers, the response does not tell whether the address was
+/// already on the list.
+///
+/// Invalid fields are reported as an RFC 7807 problem document to clients
+/// that prefer JSON.
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content(
+        (FormData = "application/x-www-form-urlencoded"),
+        (FormData = "application/json"),
+    )),
+    responses(
+        (status = 200, description = "The subscriber was added, or already on the list."),
+        SubscribeError,
+        (status = 429, description = "Too many requests from this client or for this email address.",
+            headers(("Retry-After" = u64, description = "Seconds to wait before trying again."))),
+    )
+)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pg_pool, email_client, base_url),
+    skip(form, req, pg_pool, base_url, settings, metrics, captcha),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
 )]
-
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    JsonOrForm(form): JsonOrForm<FormData>,
+    req: HttpRequest,
     pg_pool: Data<PgPool>,
-    email_client: Data<EmailClient>,
     base_url: Data<ApplicationBaseUrl>,
+    settings: Data<SubscriptionSettings>,
+    metrics: Data<Metrics>,
+    captcha: Data<Captcha>,
 ) -> Result<impl Responder, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    // Bots get the same answer as humans, they should not learn they were caught
+    if !form.website.is_empty() {
+        tracing::info!("The honeypot field was filled in, ignoring the sign-up.");
+        return Ok(HttpResponse::Ok());
+    }
+    let client_ip = req.extensions().get::<ClientIp>().map(|ip| ip.0);
+    let passed = captcha
+        .check(form.captcha_token.as_deref(), client_ip)
+        .await
+        .context("Failed to verify the CAPTCHA.")?;
+    if !passed {
+        return Err(SubscribeError::CaptchaFailed {
+            format: ErrorFormat::preferred_by(&req),
+        });
+    }
+
+    let new_subscriber: NewSubscriber =
+        form.try_into()
+            .map_err(|errors| SubscribeError::ValidationError {
+                errors,
+                format: ErrorFormat::preferred_by(&req),
+            })?;
+    // Only now that bots were turned away, so that they cannot use up the
+    // allowance of the address they put in the form
+    req.app_data::<Data<SubscriptionRateLimits>>()
+        .expect("The rate limits are not registered as application data.")
+        .check_email(&new_subscriber.email)
+        .map_err(|retry_after| SubscribeError::TooManyRequests { retry_after })?;
     let mut transaction = pg_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let suscriber_id = insert_subscriber(&mut *transaction, &new_subscriber)
-        .await
-        .context("Failed to insert new subscriber in the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut *transaction, suscriber_id, &subscription_token)
-        .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+    let (subscriber, mut is_new_subscription) =
+        match get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+            .await
+            .context("Failed to look up the subscriber in the database.")?
+        {
+            Some(subscriber) => (subscriber, false),
+            None => (
+                insert_subscriber(&mut transaction, &new_subscriber)
+                    .await
+                    .context("Failed to insert new subscriber in the database.")?,
+                true,
+            ),
+        };
+
+    match subscriber.status {
+        SubscriptionStatus::PendingConfirmation => {}
+        SubscriptionStatus::Unsubscribed => {
+            mark_subscriber_as_pending(&mut transaction, &subscriber)
+                .await
+                .context("Failed to move an unsubscribed subscriber back to pending.")?;
+            is_new_subscription = true;
+        }
+        // Already confirmed, or never to be mailed again: answer exactly as we
+        // would for a new subscriber, so that the endpoint cannot be used to
+        // find out who is on the list.
+        SubscriptionStatus::Confirmed
+        | SubscriptionStatus::Bounced
+        | SubscriptionStatus::Complained => return Ok(HttpResponse::Ok()),
+    }
+
+    let subscription_token =
+        issue_confirmation_token(&mut transaction, subscriber.id, &settings).await?;
+    enqueue_confirmation_email(
+        &mut transaction,
+        &new_subscriber.email,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await?;
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    if is_new_subscription {
+        metrics.record_subscription_created();
+    }
+    Ok(HttpResponse::Ok())
+}
 
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+}
+
+/// Send a fresh confirmation link to a pending subscriber, e.g. after the
+/// first one expired.
+///
+/// Replies with a 200 whether or not the address is pending confirmation,
+/// so that the endpoint cannot be used to find out who is on the list.
+#[tracing::instrument(
+    name = "Resending a confirmation email",
+    skip(form, pg_pool, base_url, settings, rate_limits),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pg_pool: Data<PgPool>,
+    base_url: Data<ApplicationBaseUrl>,
+    settings: Data<SubscriptionSettings>,
+    rate_limits: Data<SubscriptionRateLimits>,
+) -> Result<impl Responder, SubscribeError> {
+    let email =
+        SubscriberEmail::parse(form.0.email).map_err(|e| SubscribeError::ValidationError {
+            errors: InvalidField::from(e).into(),
+            format: ErrorFormat::PlainText,
+        })?;
+    rate_limits
+        .check_email(&email)
+        .map_err(|retry_after| SubscribeError::TooManyRequests { retry_after })?;
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = get_subscriber_by_email(&mut transaction, &email)
+        .await
+        .context("Failed to look up the subscriber in the database.")?;
+    let Some(subscriber) =
+        subscriber.filter(|s| s.status == SubscriptionStatus::PendingConfirmation)
+    else {
+        return Ok(HttpResponse::Ok());
+    };
+
+    let subscription_token =
+        issue_confirmation_token(&mut transaction, subscriber.id, &settings).await?;
+    enqueue_confirmation_email(&mut transaction, &email, &base_url.0, &subscription_token).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new confirmation token.")?;
     Ok(HttpResponse::Ok())
 }
 
+/// Replace the subscriber's confirmation tokens with a new one: links sent
+/// in previous confirmation emails stop working.
+pub async fn issue_confirmation_token(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+    settings: &SubscriptionSettings,
+) -> Result<String, anyhow::Error> {
+    delete_tokens(transaction, subscriber_id)
+        .await
+        .context("Failed to delete previous confirmation tokens.")?;
+    let subscription_token = generate_subscription_token();
+    let expires_at = Utc::now() + settings.confirmation_token_ttl();
+    store_token(transaction, subscriber_id, &subscription_token, expires_at)
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    Ok(subscription_token)
+}
+
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    #[error("{errors}")]
+    ValidationError {
+        errors: ValidationErrors,
+        format: ErrorFormat,
+    },
+    #[error("The CAPTCHA was not solved.")]
+    CaptchaFailed { format: ErrorFormat },
+    #[error("Too many requests for this email address.")]
+    TooManyRequests { retry_after: Duration },
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -114,10 +290,95 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
-            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::ValidationError { .. } | Self::CaptchaFailed { .. } => StatusCode::BAD_REQUEST,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::ValidationError {
+                errors,
+                format: ErrorFormat::ProblemJson,
+            } => ProblemDetails::invalid_params(
+                "The sign-up form has invalid fields.",
+                errors.fields().iter().map(InvalidParam::from).collect(),
+            )
+            .response(),
+            Self::CaptchaFailed {
+                format: ErrorFormat::ProblemJson,
+            } => ProblemDetails::new(self.status_code(), self.to_string()).response(),
+            Self::TooManyRequests { retry_after } => too_many_requests_response(*retry_after),
+            _ => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+        }
+    }
+}
+
+impl utoipa::IntoResponses for SubscribeError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let plain_text = |description: &str| {
+            ResponseBuilder::new()
+                .description(description)
+                .content("text/plain", Content::new(Some(String::schema())))
+                .build()
+                .into()
+        };
+        BTreeMap::from([
+            (
+                StatusCode::BAD_REQUEST.as_str().to_string(),
+                ResponseBuilder::new()
+                    .description(
+                        "The body is malformed, the name or the email is invalid, or the \
+                        CAPTCHA was not solved. The error is described by a problem document \
+                        if the client prefers JSON.",
+                    )
+                    .content("text/plain", Content::new(Some(String::schema())))
+                    .content(
+                        "application/problem+json",
+                        Content::new(Some(Ref::from_schema_name("ProblemDetails"))),
+                    )
+                    .build()
+                    .into(),
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR.as_str().to_string(),
+                plain_text("Something went wrong on our side."),
+            ),
+        ])
+    }
+}
+
+pub struct Subscriber {
+    pub id: Uuid,
+    pub status: SubscriptionStatus,
+}
+
+#[tracing::instrument(name = "Looking up subscriber by email", skip(transaction, email))]
+async fn get_subscriber_by_email(
+    transaction: &mut PgConnection,
+    email: &SubscriberEmail,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    // Lock the row: concurrent sign-ups for the same address are handled
+    // one after the other.
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
 }
 
 #[tracing::instrument(
@@ -127,29 +388,78 @@ impl ResponseError for SubscribeError {
 pub async fn insert_subscriber(
     transaction: &mut PgConnection,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+) -> Result<Subscriber, sqlx::Error> {
+    // If another request inserted the same email in the meantime we wait for
+    // it to commit and carry on with its row.
+    let inserted = sqlx::query_as!(
+        Subscriber,
         r#"
-        INSERT INTO subscriptions(id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation');
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id, status AS "status: SubscriptionStatus";
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        SubscriptionStatus::PendingConfirmation.as_str(),
+        generate_subscription_token()
     )
-    .execute(&mut *transaction)
+    .fetch_optional(&mut *transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
 
-    Ok(subscriber_id)
+    match inserted {
+        Some(subscriber) => Ok(subscriber),
+        None => get_subscriber_by_email(transaction, &new_subscriber.email)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound),
+    }
 }
 
-fn generate_subscription_token() -> String {
+#[tracing::instrument(name = "Mark subscriber as pending", skip(transaction, subscriber))]
+async fn mark_subscriber_as_pending(
+    transaction: &mut PgConnection,
+    subscriber: &Subscriber,
+) -> Result<(), anyhow::Error> {
+    let status = subscriber
+        .status
+        .transition_to(SubscriptionStatus::PendingConfirmation)
+        .map_err(anyhow::Error::msg)?;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = $1 WHERE id = $2",
+        status.as_str(),
+        subscriber.id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the status of a subscriber.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Deleting subscription tokens", skip(transaction))]
+async fn delete_tokens(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+pub fn generate_subscription_token() -> String {
     Alphanumeric.sample_string(&mut rand::rng(), 25)
 }
 
@@ -161,14 +471,16 @@ async fn store_token(
     transaction: &mut PgConnection,
     subscriber_id: Uuid,
     subscription_token: &String,
+    expires_at: DateTime<Utc>,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscriber_id, subscription_token) 
-        VALUES ($1, $2);
+        INSERT INTO subscription_tokens (subscriber_id, subscription_token, expires_at)
+        VALUES ($1, $2, $3);
         "#,
         subscriber_id,
         subscription_token,
+        expires_at,
     )
     .execute(&mut *transaction)
     .await
@@ -180,16 +492,17 @@ async fn store_token(
     Ok(())
 }
 
+/// The email is sent by the dispatcher once the transaction commits.
 #[tracing::instrument(
-    name = "Sending welcome notification to new subscriber",
-    skip(email_client, new_subscriber)
+    name = "Queueing welcome notification to new subscriber",
+    skip(transaction, recipient, subscription_token)
 )]
-async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+pub async fn enqueue_confirmation_email(
+    transaction: &mut PgConnection,
+    recipient: &SubscriberEmail,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -205,17 +518,18 @@ async fn send_confirmation_email(
         Visit {} to confirm your subscription.",
         confirmation_link
     );
-    email_client
-        .send_email(new_subscriber.email, "Welcome!", &html_body, &text_body)
+    let email = OutboxEmail {
+        recipient,
+        subject: "Welcome!",
+        html_content: &html_body,
+        text_content: text_body,
+    };
+    enqueue_email(transaction, email)
         .await
-        .map_err(|e| {
-            tracing::error!("Failed to send welcome message: {:?}", e);
-            e
-        })?;
-    Ok(())
+        .context("Failed to queue a confirmation email.")
 }
 
-fn error_chain_fmt(
+pub fn error_chain_fmt(
     e: &impl std::error::Error,
     f: &mut std::fmt::Formatter<'_>,
 ) -> std::fmt::Result {
@@ -1,68 +1,305 @@
-use actix_web::{HttpResponse, Responder, web};
+use std::collections::BTreeMap;
+
+use actix_web::{
+    HttpResponse, ResponseError,
+    http::{StatusCode, header::ContentType},
+    web,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
+use utoipa::{
+    PartialSchema,
+    openapi::{Content, RefOr, Response, ResponseBuilder},
+};
 use uuid::Uuid;
 
-#[derive(Deserialize)]
+use crate::{
+    configuration::{SubscriptionSettings, page_templates},
+    domain::SubscriptionStatus,
+    metrics::Metrics,
+    routes::error_chain_fmt,
+    utils::see_other,
+};
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters {
+    /// From the link in the confirmation email.
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pg_pool))]
-pub async fn confirm(
+/// Ask for a confirmation before confirming, so that email clients and link
+/// scanners fetching the link neither confirm the subscriber nor use up
+/// their token.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "A page asking the subscriber to confirm.", content_type = "text/html", body = String),
+        ConfirmError,
+    )
+)]
+#[tracing::instrument(name = "Show confirmation page", skip(parameters, pg_pool))]
+pub async fn confirm_form(
     parameters: web::Query<Parameters>,
     pg_pool: web::Data<PgPool>,
-) -> impl Responder {
-    match fetch_subscriber_id_from_token(&pg_pool, &parameters.subscription_token).await {
-        Ok(None) => return HttpResponse::Unauthorized(),
-        Err(_) => return HttpResponse::InternalServerError(),
-        Ok(Some(subscriber_id)) => {
-            if confirm_subscriber(&pg_pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError();
-            }
-        }
-    };
+) -> Result<HttpResponse, ConfirmError> {
+    let token = get_token(&pg_pool, &parameters.subscription_token).await?;
+    check_token(token)?;
 
-    HttpResponse::Ok()
+    let mut context = tera::Context::new();
+    context.insert("subscription_token", &parameters.subscription_token);
+    let page = render_page(StatusCode::OK, "confirmation/confirm.html", &context)
+        .context("Failed to render the confirmation page.")?;
+    Ok(page)
 }
 
+/// Confirm the subscriber the token was issued to, the token cannot be used
+/// again.
+#[utoipa::path(
+    post,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscription is confirmed.", content_type = "text/html", body = String),
+        (status = 303, description = "The subscription is confirmed, redirecting to the configured page.",
+            headers(("Location" = String))),
+        ConfirmError,
+    )
+)]
 #[tracing::instrument(
-    name = "Uses subscription token, returning subscriber",
-    skip(pool, token)
+    name = "Confirm a pending subscriber",
+    skip(parameters, pg_pool, settings, metrics)
 )]
-async fn fetch_subscriber_id_from_token(
-    pool: &PgPool,
-    token: &String,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let record = sqlx::query!(
-        r#"DELETE FROM subscription_tokens 
-        WHERE subscription_token = $1 
-        RETURNING subscriber_id;"#,
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pg_pool: web::Data<PgPool>,
+    settings: web::Data<SubscriptionSettings>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, ConfirmError> {
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = lock_token(&mut transaction, &parameters.subscription_token).await?;
+    let token = check_token(token)?;
+
+    delete_token(&mut transaction, &parameters.subscription_token).await?;
+    update_subscriber_status(
+        &mut transaction,
+        token.subscriber_id,
+        SubscriptionStatus::Confirmed,
+    )
+    .await?;
+    // Rendered before committing: failing afterwards would show an error to
+    // a subscriber who is confirmed already.
+    let response = match &settings.confirmation_redirect_url {
+        Some(url) => see_other(url),
+        None => render_page(
+            StatusCode::OK,
+            "confirmation/success.html",
+            &tera::Context::new(),
+        )
+        .context("Failed to render the confirmation success page.")?,
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    metrics.record_subscription_confirmed();
+    Ok(response)
+}
+
+/// Whether `token` can still be used to confirm its subscriber.
+fn check_token(token: Option<SubscriptionToken>) -> Result<SubscriptionToken, ConfirmError> {
+    let token = token.ok_or(ConfirmError::UnknownToken)?;
+    // Expired tokens are left for the cleanup task, so that following the
+    // link again keeps telling the subscriber to ask for a new one.
+    if token.expires_at <= Utc::now() {
+        return Err(ConfirmError::ExpiredToken);
+    }
+    // E.g. their address bounced in the meantime
+    if !token
+        .subscriber_status
+        .can_transition_to(SubscriptionStatus::Confirmed)
+    {
+        return Err(ConfirmError::UnknownToken);
+    }
+    Ok(token)
+}
+
+fn render_page(
+    status_code: StatusCode,
+    template: &str,
+    context: &tera::Context,
+) -> Result<HttpResponse, tera::Error> {
+    let body = page_templates().render(template, context)?;
+    Ok(HttpResponse::build(status_code)
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The confirmation token has expired.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl utoipa::IntoResponses for ConfirmError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let html = |description: &str| {
+            ResponseBuilder::new()
+                .description(description)
+                .content("text/html", Content::new(Some(String::schema())))
+                .build()
+                .into()
+        };
+        BTreeMap::from([
+            (
+                StatusCode::UNAUTHORIZED.as_str().to_string(),
+                html("The token is unknown, or was already used."),
+            ),
+            (
+                StatusCode::GONE.as_str().to_string(),
+                html("The token has expired, the subscriber has to ask for a new one."),
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR.as_str().to_string(),
+                html("Something went wrong on our side."),
+            ),
+        ])
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::ExpiredToken => StatusCode::GONE,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let template = match self {
+            Self::UnknownToken => "confirmation/invalid.html",
+            Self::ExpiredToken => "confirmation/expired.html",
+            Self::UnexpectedError(_) => "confirmation/error.html",
+        };
+        // Still answer, in plain text, if the pages are missing or broken
+        render_page(self.status_code(), template, &tera::Context::new()).unwrap_or_else(|e| {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to render {}.",
+                template
+            );
+            HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string())
+        })
+    }
+}
+
+struct SubscriptionToken {
+    subscriber_id: Uuid,
+    subscriber_status: SubscriptionStatus,
+    expires_at: DateTime<Utc>,
+}
+
+/// A read only lookup, for the page asking to confirm: link checkers and
+/// email scanners fetch it too.
+#[tracing::instrument(name = "Get subscription token", skip(pg_pool, token))]
+async fn get_token(
+    pg_pool: &PgPool,
+    token: &str,
+) -> Result<Option<SubscriptionToken>, anyhow::Error> {
+    let record = sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+        SELECT
+            subscription_tokens.subscriber_id,
+            subscriptions.status AS "subscriber_status: SubscriptionStatus",
+            subscription_tokens.expires_at
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscription_token = $1
+        "#,
+        token
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to look up the subscription token.")?;
+    Ok(record)
+}
+
+/// Like `get_token`, but locks the token and its subscriber until the end
+/// of `transaction`, so that they are confirmed only once.
+#[tracing::instrument(name = "Lock subscription token", skip(transaction, token))]
+async fn lock_token(
+    transaction: &mut PgConnection,
+    token: &str,
+) -> Result<Option<SubscriptionToken>, anyhow::Error> {
+    let record = sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+        SELECT
+            subscription_tokens.subscriber_id,
+            subscriptions.status AS "subscriber_status: SubscriptionStatus",
+            subscription_tokens.expires_at
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscription_token = $1
+        FOR UPDATE
+        "#,
         token
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+    .context("Failed to look up the subscription token.")?;
+    Ok(record)
+}
 
-    Ok(record.map(|r| r.subscriber_id))
+#[tracing::instrument(name = "Delete subscription token", skip(transaction, token))]
+async fn delete_token(transaction: &mut PgConnection, token: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscription_token = $1",
+        token
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete a used subscription token.")?;
+    Ok(())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool))]
-async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(name = "Update subscriber status", skip(transaction))]
+async fn update_subscriber_status(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<(), anyhow::Error> {
     sqlx::query!(
         "UPDATE subscriptions SET status = $1 WHERE id = $2",
-        "confirmed",
+        status.as_str(),
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-
+    .context("Failed to update the status of a subscriber.")?;
     Ok(())
 }
@@ -0,0 +1,146 @@
+use actix_web::{
+    HttpResponse, ResponseError,
+    http::{StatusCode, header::ContentType},
+    web,
+};
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{domain::SubscriptionStatus, routes::error_chain_fmt};
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String,
+}
+
+/// Ask for a confirmation before unsubscribing, so that email clients and
+/// link scanners fetching the link do not remove anybody from the list.
+#[tracing::instrument(name = "Show unsubscribe page", skip(parameters, pg_pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let token = &parameters.unsubscribe_token;
+    if !subscriber_exists(&pg_pool, token).await? {
+        return Err(UnsubscribeError::UnknownToken);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe?unsubscribe_token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            htmlescape::encode_attribute(token)
+        )))
+}
+
+/// Handles both the form above and RFC 8058 one-click requests, which POST
+/// `List-Unsubscribe=One-Click` to the URL in the `List-Unsubscribe` header.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pg_pool))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    if !mark_subscriber_as_unsubscribed(&pg_pool, &parameters.unsubscribe_token).await? {
+        return Err(UnsubscribeError::UnknownToken);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("unsubscribed.html")))
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Look up subscriber by unsubscribe token", skip(pool, token))]
+async fn subscriber_exists(pool: &PgPool, token: &str) -> Result<bool, anyhow::Error> {
+    let record = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE unsubscribe_token = $1",
+        token
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a subscriber by unsubscribe token.")?;
+    Ok(record.is_some())
+}
+
+/// Returns `false` if no subscriber matches `token`. Unsubscribing twice is
+/// not an error, and neither is unsubscribing an address we already stopped
+/// mailing because it bounced or complained.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool, token))]
+async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    token: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT id, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE unsubscribe_token = $1
+        FOR UPDATE
+        "#,
+        token
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up a subscriber by unsubscribe token.")?
+    else {
+        return Ok(false);
+    };
+
+    if subscriber
+        .status
+        .can_transition_to(SubscriptionStatus::Unsubscribed)
+    {
+        sqlx::query!(
+            "UPDATE subscriptions SET status = $1 WHERE id = $2",
+            SubscriptionStatus::Unsubscribed.as_str(),
+            subscriber.id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to mark a subscriber as unsubscribed.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    Ok(true)
+}
@@ -0,0 +1,201 @@
+use actix_web::{HttpResponse, ResponseError, http::StatusCode, web};
+use anyhow::Context;
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{domain::SubscriptionStatus, routes::error_chain_fmt};
+
+/// The Postmark webhook payloads we act upon, told apart by `RecordType`.
+#[derive(Deserialize)]
+#[serde(tag = "RecordType", rename_all_fields = "PascalCase")]
+enum PostmarkEvent {
+    Bounce {
+        r#type: String,
+        email: String,
+    },
+    SpamComplaint {
+        email: String,
+    },
+    SubscriptionChange {
+        recipient: String,
+        suppress_sending: bool,
+        suppression_reason: Option<String>,
+    },
+    /// Deliveries, opens, clicks, ...
+    #[serde(other)]
+    Other,
+}
+
+impl PostmarkEvent {
+    fn record_type(&self) -> &'static str {
+        match self {
+            Self::Bounce { .. } => "Bounce",
+            Self::SpamComplaint { .. } => "SpamComplaint",
+            Self::SubscriptionChange { .. } => "SubscriptionChange",
+            Self::Other => "Other",
+        }
+    }
+
+    fn email(&self) -> Option<&str> {
+        match self {
+            Self::Bounce { email, .. } | Self::SpamComplaint { email } => Some(email),
+            Self::SubscriptionChange { recipient, .. } => Some(recipient),
+            Self::Other => None,
+        }
+    }
+
+    /// The status the subscriber moves to, if the event says we must stop
+    /// mailing them. Soft bounces (full mailbox, auto-responders, ...) and
+    /// reactivations are only recorded.
+    fn new_status(&self) -> Option<SubscriptionStatus> {
+        match self {
+            Self::Bounce { r#type, .. } if HARD_BOUNCE_TYPES.contains(&r#type.as_str()) => {
+                Some(SubscriptionStatus::Bounced)
+            }
+            Self::SpamComplaint { .. } => Some(SubscriptionStatus::Complained),
+            Self::SubscriptionChange {
+                suppress_sending: true,
+                suppression_reason,
+                ..
+            } => match suppression_reason.as_deref() {
+                Some("HardBounce") => Some(SubscriptionStatus::Bounced),
+                Some("SpamComplaint") => Some(SubscriptionStatus::Complained),
+                _ => Some(SubscriptionStatus::Unsubscribed),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Bounce types for which Postmark deactivates the recipient.
+const HARD_BOUNCE_TYPES: &[&str] = &["HardBounce", "BadEmailAddress"];
+
+/// Receives Postmark's bounce, spam complaint and subscription change
+/// webhooks. Other record types are acknowledged and ignored, replying with
+/// anything but a 2xx would make Postmark retry them.
+#[tracing::instrument(
+    name = "Handling a Postmark webhook",
+    skip(body, pg_pool),
+    fields(record_type = tracing::field::Empty, subscriber_email = tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+    body: web::Json<serde_json::Value>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, WebhookError> {
+    let payload = body.into_inner();
+    let event = PostmarkEvent::deserialize(&payload)
+        .map_err(|e| WebhookError::ValidationError(e.to_string()))?;
+    let Some(email) = event.email() else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    tracing::Span::current()
+        .record("record_type", event.record_type())
+        .record("subscriber_email", email);
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    store_event(&mut transaction, event.record_type(), email, &payload).await?;
+    if let Some(status) = event.new_status() {
+        update_subscriber_status(&mut transaction, email, status).await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a webhook event.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Store email event", skip(transaction, email, payload))]
+async fn store_event(
+    transaction: &mut PgConnection,
+    record_type: &str,
+    email: &str,
+    payload: &serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (id, record_type, email, payload, received_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        record_type,
+        email,
+        payload,
+        Utc::now()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store an email event.")?;
+    Ok(())
+}
+
+/// Unknown addresses are not an error: the subscriber may have been purged
+/// since the email was sent. Neither are illegal transitions, e.g. a
+/// complaint about an address that already bounced: the event is recorded
+/// and the status left as it is.
+///
+/// Providers may not report the address with the case it was signed up
+/// with, so it is matched case-insensitively.
+#[tracing::instrument(name = "Update subscriber status", skip(transaction, email))]
+async fn update_subscriber_status(
+    transaction: &mut PgConnection,
+    email: &str,
+    status: SubscriptionStatus,
+) -> Result<(), anyhow::Error> {
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT id, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        FOR UPDATE
+        "#,
+        email
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to look up a subscriber by email.")?;
+
+    for subscriber in subscribers {
+        if let Err(e) = subscriber.status.transition_to(status) {
+            tracing::info!("Leaving the subscriber status unchanged: {}", e);
+            continue;
+        }
+
+        sqlx::query!(
+            "UPDATE subscriptions SET status = $1 WHERE id = $2",
+            status.as_str(),
+            subscriber.id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the status of a subscriber.")?;
+    }
+    Ok(())
+}
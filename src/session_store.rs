@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use actix_session::storage::{
+    LoadError, SaveError, SessionKey, SessionStore, UpdateError, generate_session_key,
+};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use sqlx::PgPool;
+
+type SessionState = HashMap<String, String>;
+
+/// Server-side session storage backed by the `sessions` table.
+///
+/// Only the session key travels in the cookie, the state itself never
+/// leaves Postgres. Expired rows are ignored on load and purged whenever a
+/// new session is created.
+#[derive(Clone)]
+pub struct PgSessionStore {
+    pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT session_state
+            FROM sessions
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to load session state.")
+        .map_err(LoadError::Other)?;
+
+        row.map(|r| serde_json::from_value(r.session_state))
+            .transpose()
+            .context("Failed to deserialize session state.")
+            .map_err(LoadError::Deserialization)
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let session_state = serde_json::to_value(session_state)
+            .context("Failed to serialize session state.")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+
+        sqlx::query!("DELETE FROM sessions WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .context("Failed to purge expired sessions.")
+            .map_err(SaveError::Other)?;
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, session_state, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            "#,
+            session_key.as_ref(),
+            session_state,
+            ttl.as_seconds_f64(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to save session state.")
+        .map_err(SaveError::Other)?;
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let serialized_state = serde_json::to_value(&session_state)
+            .context("Failed to serialize session state.")
+            .map_err(UpdateError::Serialization)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET session_state = $2, expires_at = now() + make_interval(secs => $3)
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref(),
+            serialized_state,
+            ttl.as_seconds_f64(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update session state.")
+        .map_err(UpdateError::Other)?;
+
+        if result.rows_affected() == 0 {
+            // The session expired in the meantime: start a new one
+            // rather than resurrecting the old key.
+            return self.save(session_state, ttl).await.map_err(|e| match e {
+                SaveError::Serialization(e) => UpdateError::Serialization(e),
+                SaveError::Other(e) => UpdateError::Other(e),
+            });
+        }
+        Ok(session_key)
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET expires_at = now() + make_interval(secs => $2)
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            ttl.as_seconds_f64(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update session TTL.")?;
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            "DELETE FROM sessions WHERE session_key = $1",
+            session_key.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete session.")?;
+        Ok(())
+    }
+}
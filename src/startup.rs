@@ -1,22 +1,58 @@
 use std::{io::Error, net::TcpListener};
 
+use actix_session::SessionMiddleware;
 use actix_web::{
     App, HttpServer,
+    cookie::Key,
     dev::Server,
+    middleware::from_fn,
     web::{self, Data},
 };
+use actix_web_flash_messages::{FlashMessagesFramework, storage::CookieMessageStore};
+use secrecy::ExposeSecret;
 use sqlx::{PgPool, postgres::PgPoolOptions};
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    authentication::{
+        create_initial_admin, reject_anonymous_callers, reject_anonymous_users,
+        reject_invalid_api_keys, reject_unknown_webhook_callers,
+    },
+    captcha::Captcha,
+    configuration::{
+        DatabaseSettings, Settings, SubscriptionSettings, email_templates, page_templates,
+    },
     email_client::EmailClient,
-    routes::{confirm, health_check, subscribe},
+    email_dispatcher::run_dispatcher_until_stopped,
+    issue_delivery_worker::run_worker_until_stopped,
+    metrics::{Metrics, record_request_metrics},
+    problem_details::problem_input_error,
+    rate_limit::{SubscriptionRateLimits, rate_limit_subscriptions},
+    routes::{
+        admin_dashboard, api_input_error, api_keys_form, change_password, change_password_form,
+        confirm, confirm_form, create_api_key, create_subscriber, delete_subscriber,
+        get_subscriber, health_check, home, list_subscribers, log_out, login, login_form,
+        metrics as metrics_endpoint, openapi_spec, postmark_webhook, publish_newsletter, readiness,
+        resend_confirmation, revoke_api_key, subscribe, unsubscribe, unsubscribe_form,
+        update_subscriber,
+    },
+    session_store::PgSessionStore,
+    subscription_cleanup_worker::run_cleanup_until_stopped,
 };
 
 pub struct Application {
     port: u16,
     server: Server,
+    admin_port: Option<u16>,
+    admin_server: Option<Server>,
+    connection_pool: PgPool,
+    email_client: EmailClient,
+    dispatcher_email_client: EmailClient,
+    base_url: String,
+    subscription_settings: SubscriptionSettings,
+    background_workers: bool,
+    metrics: Metrics,
 }
 
 pub struct ApplicationBaseUrl(pub String);
@@ -25,23 +61,33 @@ impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
+        let metrics = Metrics::new();
+
+        // Fail now, rather than on the first request, if templates are broken
+        email_templates();
+        page_templates();
+
         // Email Client
-        let sender = configuration
+        let email_client = configuration
             .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
+            .clone()
+            .client()
+            .with_metrics(metrics.clone());
 
         // Migrate the DB
         let migration = sqlx::migrate!().run(&connection_pool).await;
         tracing::info!("migrations result: {:?}", migration);
 
+        if let Some(initial_admin) = configuration.initial_admin.clone() {
+            let username = initial_admin.username.clone();
+            if create_initial_admin(initial_admin.credentials(), &connection_pool)
+                .await
+                .map_err(Error::other)?
+            {
+                tracing::info!("Created the initial admin, {}", username);
+            }
+        }
+
         // Listen to port
         let address = format!(
             "{}:{}",
@@ -50,21 +96,154 @@ impl Application {
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
 
+        // Metrics are never served on the public port
+        let (admin_port, admin_server) = match configuration.application.admin_port {
+            Some(admin_port) => {
+                let address = format!("{}:{}", configuration.application.host, admin_port);
+                let listener = TcpListener::bind(address)?;
+                let admin_port = listener.local_addr().unwrap().port();
+                let admin_server = run_admin(listener, connection_pool.clone(), metrics.clone())?;
+                (Some(admin_port), Some(admin_server))
+            }
+            None => (None, None),
+        };
+
         let server = run(
             listener,
-            connection_pool,
+            connection_pool.clone(),
             email_client,
-            configuration.application.base_url,
+            metrics.clone(),
+            configuration.clone(),
         )?;
-        Ok(Self { port, server })
+
+        // The delivery worker and the dispatcher get their own clients, the
+        // first one is owned by the server
+        let email_client = configuration
+            .email_client
+            .clone()
+            .client()
+            .with_metrics(metrics.clone());
+        let dispatcher_email_client = configuration
+            .email_client
+            .client()
+            .with_metrics(metrics.clone());
+        Ok(Self {
+            port,
+            server,
+            admin_port,
+            admin_server,
+            connection_pool,
+            email_client,
+            dispatcher_email_client,
+            base_url: configuration.application.base_url,
+            subscription_settings: configuration.subscriptions,
+            background_workers: configuration.application.background_workers,
+            metrics,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The port serving `/metrics`, if any.
+    pub fn admin_port(&self) -> Option<u16> {
+        self.admin_port
+    }
+
+    /// The metrics served on `/metrics`, for email clients running the
+    /// background tasks out of the application.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Serve HTTP requests while the issue delivery worker drains the queue,
+    /// the dispatcher sends the emails in the outbox and unconfirmed
+    /// subscriptions are purged in the background, until we get Ctrl+C or
+    /// SIGTERM.
     pub async fn run_until_stopped(self) -> Result<(), Error> {
-        self.server.await
+        self.run_until(shutdown_signal()).await
+    }
+
+    /// Like `run_until_stopped`, shutting down once `shutdown_signal`
+    /// completes: we stop accepting connections, give in-flight requests up
+    /// to the grace period to complete, let the background workers finish
+    /// their current task, then close the connection pool.
+    ///
+    /// Returns early if any of them fails. The background workers are only
+    /// started if enabled in the settings.
+    pub async fn run_until(self, shutdown_signal: impl Future<Output = ()>) -> Result<(), Error> {
+        let shutdown = CancellationToken::new();
+        let cleanup = run_cleanup_until_stopped(
+            self.connection_pool.clone(),
+            self.subscription_settings,
+            shutdown.clone(),
+        );
+        let worker = run_worker_until_stopped(
+            self.connection_pool.clone(),
+            self.email_client,
+            self.base_url,
+            shutdown.clone(),
+        );
+        let dispatcher = run_dispatcher_until_stopped(
+            self.connection_pool.clone(),
+            self.dispatcher_email_client,
+            shutdown.clone(),
+        );
+        let background_workers = async {
+            if !self.background_workers {
+                return Ok(());
+            }
+            tokio::try_join!(worker, dispatcher, cleanup)
+                .map(|_| ())
+                .map_err(Error::other)
+        };
+        let server_handle = self.server.handle();
+        let admin_server_handle = self.admin_server.as_ref().map(Server::handle);
+        let admin_server = async {
+            match self.admin_server {
+                Some(admin_server) => admin_server.await,
+                None => Ok(()),
+            }
+        };
+        let stop = async {
+            shutdown_signal.await;
+            tracing::info!("Shutting down.");
+            shutdown.cancel();
+            if let Some(admin_server_handle) = admin_server_handle {
+                admin_server_handle.stop(true).await;
+            }
+            server_handle.stop(true).await;
+            Ok(())
+        };
+
+        let outcome = tokio::try_join!(self.server, admin_server, background_workers, stop,);
+        self.connection_pool.close().await;
+        outcome.map(|_| ())
+    }
+}
+
+/// Completes on Ctrl+C, or on the SIGTERM Fly sends before stopping a
+/// machine.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C.");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM.")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }
 
@@ -76,21 +255,152 @@ pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
+    metrics: Metrics,
+    configuration: Settings,
 ) -> Result<Server, std::io::Error> {
+    let Settings {
+        application: application_settings,
+        subscriptions: subscription_settings,
+        webhooks: webhook_settings,
+        health: health_settings,
+        captcha: captcha_settings,
+        ..
+    } = configuration;
+    let shutdown_grace_period = application_settings.shutdown_grace_period();
+    let secret_key = Key::from(application_settings.hmac_secret.expose_secret().as_bytes());
+    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_framework = FlashMessagesFramework::builder(message_store).build();
+    let session_store = PgSessionStore::new(db_pool.clone());
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
-    let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let base_url = Data::new(ApplicationBaseUrl(application_settings.base_url));
+    let subscription_settings = Data::new(subscription_settings);
+    let webhook_settings = Data::new(webhook_settings);
+    let health_settings = Data::new(health_settings);
+    let metrics = Data::new(metrics);
+    let captcha = Data::new(match captcha_settings {
+        Some(settings) => Captcha::new(settings.verifier()),
+        None => Captcha::disabled(),
+    });
+    let rate_limits = Data::new(SubscriptionRateLimits::new(
+        &application_settings.rate_limit,
+    ));
     let app = move || {
         App::new()
+            .wrap(message_framework.clone())
+            .wrap(SessionMiddleware::new(
+                session_store.clone(),
+                secret_key.clone(),
+            ))
             .wrap(TracingLogger::default())
+            .wrap(from_fn(record_request_metrics))
+            .route("/", web::get().to(home))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/health/live", web::get().to(health_check))
+            .route("/health/ready", web::get().to(readiness))
+            .route("/openapi.json", web::get().to(openapi_spec))
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(from_fn(rate_limit_subscriptions))
+                    .app_data(web::JsonConfig::default().error_handler(problem_input_error))
+                    .app_data(web::FormConfig::default().error_handler(problem_input_error))
+                    .route(web::post().to(subscribe)),
+            )
+            .route("/subscriptions/confirm", web::get().to(confirm_form))
+            .route("/subscriptions/confirm", web::post().to(confirm))
+            .service(
+                web::resource("/subscriptions/resend")
+                    .wrap(from_fn(rate_limit_subscriptions))
+                    .route(web::post().to(resend_confirmation)),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .service(
+                web::resource("/newsletters")
+                    .wrap(from_fn(reject_anonymous_callers))
+                    .route(web::post().to(publish_newsletter)),
+            )
+            .service(
+                web::resource("/webhooks/postmark")
+                    .wrap(from_fn(reject_unknown_webhook_callers))
+                    .route(web::post().to(postmark_webhook)),
+            )
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password))
+                    .route("/api_keys", web::get().to(api_keys_form))
+                    .route("/api_keys", web::post().to(create_api_key))
+                    .route(
+                        "/api_keys/{api_key_id}/revoke",
+                        web::post().to(revoke_api_key),
+                    )
+                    .route("/logout", web::post().to(log_out)),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_keys))
+                    .app_data(web::JsonConfig::default().error_handler(api_input_error))
+                    .app_data(web::QueryConfig::default().error_handler(api_input_error))
+                    .app_data(web::PathConfig::default().error_handler(api_input_error))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers", web::post().to(create_subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(get_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::patch().to(update_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::delete().to(delete_subscriber),
+                    ),
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(subscription_settings.clone())
+            .app_data(webhook_settings.clone())
+            .app_data(health_settings.clone())
+            .app_data(metrics.clone())
+            .app_data(rate_limits.clone())
+            .app_data(captcha.clone())
     };
-    let server = HttpServer::new(app).listen(listener)?.run();
+    // Signals are handled by `Application::run_until_stopped`
+    let server = HttpServer::new(app)
+        .disable_signals()
+        .shutdown_timeout(shutdown_grace_period.as_secs())
+        .listen(listener)?
+        .run();
+    Ok(server)
+}
+
+/// The admin server, only exposing `/metrics`.
+pub fn run_admin(
+    listener: TcpListener,
+    db_pool: PgPool,
+    metrics: Metrics,
+) -> Result<Server, std::io::Error> {
+    let db_pool = Data::new(db_pool);
+    let metrics = Data::new(metrics);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("/metrics", web::get().to(metrics_endpoint))
+            .app_data(db_pool.clone())
+            .app_data(metrics.clone())
+    })
+    .disable_signals()
+    .listen(listener)?
+    .run();
     Ok(server)
 }
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+
+use crate::{configuration::SubscriptionSettings, domain::SubscriptionStatus};
+
+/// Periodically purge confirmation tokens that expired more than
+/// `unconfirmed_retention` ago, together with the pending subscribers they
+/// leave behind, until `shutdown` is cancelled.
+pub async fn run_cleanup_until_stopped(
+    pool: PgPool,
+    settings: SubscriptionSettings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        // Failures are logged by `purge_unconfirmed_subscriptions`, we just
+        // try again at the next tick.
+        let _ = purge_unconfirmed_subscriptions(&pool, settings.unconfirmed_retention()).await;
+        tokio::select! {
+            _ = tokio::time::sleep(settings.cleanup_interval()) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+    Ok(())
+}
+
+pub struct PurgeOutcome {
+    pub n_deleted_tokens: u64,
+    pub n_deleted_subscribers: u64,
+}
+
+#[tracing::instrument(
+    skip(pool),
+    fields(
+        n_deleted_tokens = tracing::field::Empty,
+        n_deleted_subscribers = tracing::field::Empty
+    ),
+    err
+)]
+pub async fn purge_unconfirmed_subscriptions(
+    pool: &PgPool,
+    retention: Duration,
+) -> Result<PurgeOutcome, anyhow::Error> {
+    let cutoff = Utc::now() - retention;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let n_deleted_tokens = sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE expires_at < $1",
+        cutoff
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete expired subscription tokens.")?
+    .rows_affected();
+    // A pending subscriber with no tokens left never confirmed, and cannot
+    // anymore without subscribing again.
+    let n_deleted_subscribers = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE
+            status = $2 AND
+            subscribed_at < $1 AND
+            NOT EXISTS (
+                SELECT 1 FROM subscription_tokens
+                WHERE subscription_tokens.subscriber_id = subscriptions.id
+            )
+        "#,
+        cutoff,
+        SubscriptionStatus::PendingConfirmation.as_str()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete abandoned pending subscribers.")?
+    .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to purge unconfirmed subscriptions.")?;
+
+    tracing::Span::current()
+        .record("n_deleted_tokens", n_deleted_tokens)
+        .record("n_deleted_subscribers", n_deleted_subscribers);
+    Ok(PurgeOutcome {
+        n_deleted_tokens,
+        n_deleted_subscribers,
+    })
+}
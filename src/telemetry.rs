@@ -1,8 +1,25 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_http::HeaderInjector;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracer, SdkTracerProvider},
+};
+use reqwest::header::HeaderMap;
+use tokio::task::JoinHandle;
 use tracing::{Subscriber, subscriber::set_global_default};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt};
 
+use crate::configuration::TelemetrySettings;
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
 /// Compose multiple layers into a `tracing`'s subscriber.
 ///
 /// # Implementation Notes
@@ -13,10 +30,14 @@ use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::Subscriber
 /// We need to explicitly call out that the returned subscriber is
 /// `Send` and `Sync` to make it possible to pass it to `init_subscriber`
 /// later on.
+///
+/// Spans are also handed over to OpenTelemetry if we are given a `tracer`,
+/// see `get_tracer`.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    tracer: Option<SdkTracer>,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
@@ -28,6 +49,78 @@ where
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Build an OpenTelemetry tracer exporting spans over OTLP/HTTP if
+/// `settings` has an endpoint, and keeping them in process otherwise.
+///
+/// It is registered as the global tracer provider, together with the W3C
+/// trace context propagator used for incoming and outgoing requests.
+/// It should only be called once!
+pub fn get_tracer(name: String, settings: &TelemetrySettings) -> Result<SdkTracer, anyhow::Error> {
+    let mut builder = SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            settings.sampling_ratio,
+        ))))
+        .with_resource(Resource::builder().with_service_name(name.clone()).build());
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?;
+        builder = builder.with_batch_exporter(exporter);
+    }
+    let provider = builder.build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider.clone());
+    let tracer = provider.tracer(name);
+    TRACER_PROVIDER
+        .set(provider)
+        .map_err(|_| anyhow::anyhow!("The tracer provider is already set."))?;
+    Ok(tracer)
+}
+
+/// Export the spans that are still buffered, to be called before exiting.
+pub fn shutdown_tracer_provider() {
+    if let Some(provider) = TRACER_PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to shut down the tracer provider."
+        );
+    }
+}
+
+/// The `traceparent` header carrying the current span to the services we
+/// call, empty if spans are not handed over to OpenTelemetry.
+pub fn trace_context_headers() -> HeaderMap {
+    let context = tracing::Span::current().context();
+    let mut headers = HeaderMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+/// The `traceparent` of the current span, for work carried on later, e.g. by
+/// a background worker, to resume its trace with `resume_trace`.
+pub fn current_traceparent() -> Option<String> {
+    trace_context_headers()
+        .get("traceparent")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Attach `span` to the trace `traceparent` belongs to. Only works before
+/// the span is first entered.
+pub fn resume_trace(span: &tracing::Span, traceparent: &str) {
+    let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    let _ = span.set_parent(context);
 }
 
 /// Register a subscriber as global default to process span data.
@@ -37,3 +130,14 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+/// Run a CPU-intensive closure on the blocking thread pool, keeping it
+/// attached to the span of the caller.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
@@ -0,0 +1,141 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+/// Create an API key through the admin area, returning it.
+async fn create_api_key_as_admin(app: &TestApp) -> String {
+    let response = app
+        .post_api_keys(&serde_json::json!({ "name": "crm" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Cookies are stored by the browser, the key must not end up in one
+    let cookies: Vec<String> = response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap().to_string())
+        .collect();
+    let html_page = response.text().await.unwrap();
+    let start = html_page.find("z2p_").expect("The new key is not shown.");
+    let api_key = html_page[start..start + 44].to_string();
+    for cookie in cookies {
+        assert!(!cookie.contains(&api_key));
+    }
+    api_key
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_keys() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_api_keys(&serde_json::json!({ "name": "crm" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn requests_without_an_api_key_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/subscribers", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], 401);
+}
+
+#[tokio::test]
+async fn requests_with_an_unknown_api_key_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = format!("z2p_{}", Uuid::new_v4().simple());
+
+    // Act
+    let response = app
+        .api_request(Method::GET, "/subscribers", &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn keys_created_in_the_admin_area_are_shown_once_and_grant_access() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create a key
+    let api_key = create_api_key_as_admin(&app).await;
+
+    // Act - Part 2 - Use it
+    let response = app
+        .api_request(Method::GET, "/subscribers", &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 3 - Reload the page
+    let html_page = app.get_api_keys_html().await;
+    assert!(html_page.contains("<td>crm</td>"));
+    assert!(!html_page.contains(&api_key));
+
+    // Only a hash is stored
+    let stored_hash = sqlx::query_scalar!("SELECT key_hash FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored_hash, api_key);
+}
+
+#[tokio::test]
+async fn revoked_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = create_api_key_as_admin(&app).await;
+    let api_key_id = sqlx::query_scalar!("SELECT api_key_id FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - Revoke the key
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/api_keys/{}/revoke",
+            &app.address, api_key_id
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/api_keys");
+
+    // Act - Part 2 - Try to use it
+    let response = app
+        .api_request(Method::GET, "/subscribers", &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
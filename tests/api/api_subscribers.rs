@@ -0,0 +1,418 @@
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+
+async fn create_subscriber(
+    app: &TestApp,
+    api_key: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    app.api_request(Method::POST, "/subscribers", api_key)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Add a confirmed subscriber through the API, returning its id.
+async fn create_subscriber_skipping_confirmation(
+    app: &TestApp,
+    api_key: &str,
+    email: &str,
+) -> String {
+    let response = create_subscriber(
+        app,
+        api_key,
+        serde_json::json!({
+            "email": email,
+            "name": "le guin",
+            "skip_confirmation": true
+        }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["id"].as_str().unwrap().to_string()
+}
+
+async fn get_json(app: &TestApp, api_key: &str, path: &str) -> (u16, serde_json::Value) {
+    let response = app
+        .api_request(Method::GET, path, api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    (
+        response.status().as_u16(),
+        response.json().await.unwrap_or_default(),
+    )
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_sends_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = create_subscriber(
+        &app,
+        &api_key,
+        serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin"
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["status"], "pending_confirmation");
+    app.dispatch_outbox_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = app.post_confirmation(confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_created_skipping_confirmation_are_confirmed_without_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let id =
+        create_subscriber_skipping_confirmation(&app, &api_key, "ursula_le_guin@gmail.com").await;
+
+    // Assert
+    let (status, body) = get_json(&app, &api_key, &format!("/subscribers/{}", id)).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "confirmed");
+    assert_eq!(body["name"], "le guin");
+    app.dispatch_outbox_emails().await;
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_returns_a_location() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = create_subscriber(
+        &app,
+        &api_key,
+        serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin",
+            "skip_confirmation": true
+        }),
+    )
+    .await;
+
+    // Assert
+    let location = response.headers()["Location"].to_str().unwrap().to_string();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        location,
+        format!("/api/v1/subscribers/{}", body["id"].as_str().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_twice_is_a_conflict() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    create_subscriber_skipping_confirmation(&app, &api_key, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = create_subscriber(
+        &app,
+        &api_key,
+        serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "someone else",
+            "skip_confirmation": true
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_returns_a_problem_document_for_invalid_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"email": "not-an-email", "name": "le guin"}),
+            "invalid email",
+            vec!["email"],
+        ),
+        (
+            serde_json::json!({"email": "ursula_le_guin@gmail.com", "name": ""}),
+            "empty name",
+            vec!["name"],
+        ),
+        (
+            serde_json::json!({"email": "", "name": ""}),
+            "empty name and email",
+            vec!["name", "email"],
+        ),
+        (
+            serde_json::json!({"name": "le guin"}),
+            "missing email",
+            vec![],
+        ),
+    ];
+
+    for (body, description, invalid_fields) in test_cases {
+        // Act
+        let response = create_subscriber(&app, &api_key, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not return a 400 when the payload had an {}.",
+            description
+        );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert!(
+            problem["detail"].is_string(),
+            "The API did not explain the {}.",
+            description
+        );
+        let reported_fields: Vec<_> = problem["invalid-params"]
+            .as_array()
+            .map(|params| params.iter().map(|p| p["name"].clone()).collect())
+            .unwrap_or_default();
+        assert_eq!(reported_fields, invalid_fields, "For an {}.", description);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_page_by_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for i in 0..3 {
+        create_subscriber_skipping_confirmation(&app, &api_key, &format!("ursula{}@gmail.com", i))
+            .await;
+    }
+
+    // Act
+    let (status, first_page) = get_json(&app, &api_key, "/subscribers?per_page=2").await;
+    let (_, second_page) = get_json(&app, &api_key, "/subscribers?per_page=2&page=2").await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(first_page["total"], 3);
+    assert_eq!(first_page["subscribers"].as_array().unwrap().len(), 2);
+    assert_eq!(second_page["subscribers"].as_array().unwrap().len(), 1);
+    // Most recent first
+    assert_eq!(first_page["subscribers"][0]["email"], "ursula2@gmail.com");
+    assert_eq!(second_page["subscribers"][0]["email"], "ursula0@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    create_subscriber_skipping_confirmation(&app, &api_key, "confirmed@gmail.com").await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_subscriber(
+        &app,
+        &api_key,
+        serde_json::json!({"email": "pending@gmail.com", "name": "le guin"}),
+    )
+    .await;
+
+    // Act
+    let (status, body) = get_json(&app, &api_key, "/subscribers?status=pending_confirmation").await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["subscribers"][0]["email"], "pending@gmail.com");
+    app.dispatch_outbox_emails().await;
+}
+
+#[tokio::test]
+async fn invalid_list_parameters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    for query in ["status=pending", "page=0", "per_page=101", "page=first"] {
+        // Act
+        let (status, body) = get_json(&app, &api_key, &format!("/subscribers?{}", query)).await;
+
+        // Assert
+        assert_eq!(status, 400, "{} was not rejected.", query);
+        assert!(body["detail"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn unknown_subscribers_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let (status, body) =
+        get_json(&app, &api_key, &format!("/subscribers/{}", Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(status, 404);
+    assert!(body["detail"].is_string());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_renamed_and_unsubscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let id =
+        create_subscriber_skipping_confirmation(&app, &api_key, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .api_request(Method::PATCH, &format!("/subscribers/{}", id), &api_key)
+        .json(&serde_json::json!({"name": "Ursula K. Le Guin", "status": "unsubscribed"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let (_, body) = get_json(&app, &api_key, &format!("/subscribers/{}", id)).await;
+    assert_eq!(body["name"], "Ursula K. Le Guin");
+    assert_eq!(body["status"], "unsubscribed");
+}
+
+#[tokio::test]
+async fn illegal_status_changes_are_a_conflict() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let id =
+        create_subscriber_skipping_confirmation(&app, &api_key, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .api_request(Method::PATCH, &format!("/subscribers/{}", id), &api_key)
+        .json(&serde_json::json!({"status": "pending_confirmation"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let (_, body) = get_json(&app, &api_key, &format!("/subscribers/{}", id)).await;
+    assert_eq!(body["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_gone() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    // Signed up through the form, so that they have a confirmation token
+    create_confirmed_subscriber(&app).await;
+    let (_, body) = get_json(&app, &api_key, "/subscribers").await;
+    let id = body["subscribers"][0]["id"].as_str().unwrap().to_string();
+
+    // Act
+    let response = app
+        .api_request(Method::DELETE, &format!("/subscribers/{}", id), &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let (status, _) = get_json(&app, &api_key, &format!("/subscribers/{}", id)).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_get_no_pending_email_and_leave_no_events() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    // The confirmation email stays in the outbox
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+    let response = create_subscriber(
+        &app,
+        &api_key,
+        serde_json::json!({"email": "ursula_le_guin@gmail.com", "name": "le guin"}),
+    )
+    .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let id = body["id"].as_str().unwrap().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (id, record_type, email, payload, received_at)
+        VALUES ($1, 'Delivery', 'ursula_le_guin@gmail.com', '{}', now())
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .api_request(Method::DELETE, &format!("/subscribers/{}", id), &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let n_emails = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_emails, 0);
+    let n_events = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 0);
+}
@@ -1,4 +1,11 @@
-use crate::helpers::spawn_app;
+use std::time::Duration;
+
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -6,7 +13,7 @@ async fn health_check_works() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .expect("Failted to execute request.");
@@ -14,3 +21,110 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn liveness_does_not_depend_on_anything() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!("DROP TABLE _sqlx_migrations;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn readiness_reports_each_dependency() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_readiness().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "up");
+    for dependency in ["database", "migrations"] {
+        assert_eq!(body["checks"][dependency]["status"], "up");
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+    // Not probed unless enabled
+    assert!(body["checks"].get("email_provider").is_none());
+}
+
+#[tokio::test]
+async fn readiness_fails_if_a_migration_is_not_applied() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations)"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.get_readiness().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["migrations"]["status"], "down");
+}
+
+#[tokio::test]
+async fn readiness_probes_the_email_provider_when_enabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.health.probe_email_provider = true).await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.get_readiness().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "up");
+}
+
+#[tokio::test]
+async fn readiness_fails_if_the_email_provider_does_not_answer_in_time() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.health.probe_email_provider = true;
+        c.health.timeout_milliseconds = 100;
+    })
+    .await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.get_readiness().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["email_provider"]["status"], "down");
+}
@@ -1,43 +1,101 @@
 use std::env;
 use std::sync::LazyLock;
 
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use linkify::{LinkFinder, LinkKind};
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::Connection;
 use sqlx::{Executor, PgConnection, PgPool};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{DatabaseSettings, get_configuration};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::authentication::create_api_key;
+use zero2prod::configuration::{
+    CaptchaSettings, DatabaseSettings, EmailProvider, Settings, TelemetrySettings, WebhookSettings,
+    get_configuration,
+};
+use zero2prod::email_client::EmailClient;
+use zero2prod::email_dispatcher::try_dispatch_email;
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, try_execute_task};
 use zero2prod::startup::{Application, get_connection_pool};
 use zero2prod::telemetry;
 
 static TRACING: LazyLock<()> = LazyLock::new(|| {
     let default_filter_level = "info".into();
     let subscriber_name = "test".to_string();
+    // Spans stay in process, but still carry a trace context we can check
+    let telemetry_settings = TelemetrySettings {
+        otlp_endpoint: None,
+        sampling_ratio: 1.0,
+    };
+    let tracer = telemetry::get_tracer(subscriber_name.clone(), &telemetry_settings)
+        .expect("Failed to build the OpenTelemetry tracer.");
 
     if env::var("TEST_LOG").is_ok() {
-        let subscriber =
-            telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = telemetry::get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            Some(tracer),
+        );
         telemetry::init_subscriber(subscriber);
     } else {
-        let subscriber =
-            telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = telemetry::get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            Some(tracer),
+        );
         telemetry::init_subscriber(subscriber);
     }
 });
 
 /// Spin up an instance of our app
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spin up an instance of our app requiring a CAPTCHA, checked by
+/// `captcha_server`
+pub async fn spawn_app_with_captcha() -> TestApp {
+    spawn_app_with(|c| {
+        c.captcha = Some(CaptchaSettings {
+            // Pointed at `captcha_server` once it is up
+            verify_url: String::new(),
+            secret_key: SecretString::from("captcha-secret"),
+            timeout_milliseconds: 1000,
+        })
+    })
+    .await
+}
+
+/// Spin up an instance of our app, tweaking its configuration first
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     LazyLock::force(&TRACING);
 
     // Lauch a mock server to stand in for Postmark's API
     let email_server = MockServer::start().await;
+    // And another one for the CAPTCHA provider's
+    let captcha_server = MockServer::start().await;
 
     let configuration = {
         let mut c = get_configuration().expect("Failed to read configuration.");
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.application.admin_port = Some(0);
+        // Tests run the background tasks themselves, one step at a time
+        c.application.background_workers = false;
+        c.email_client.provider = EmailProvider::Postmark;
         c.email_client.base_url = email_server.uri();
+        // Tests log in as their own `test_user`
+        c.initial_admin = None;
+        configure(&mut c);
+        if let Some(captcha) = c.captcha.as_mut() {
+            captcha.verify_url = format!("{}/siteverify", captcha_server.uri());
+        }
         c
     };
 
@@ -47,15 +105,40 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
+    let admin_address = application
+        .admin_port()
+        .map(|port| format!("http://127.0.0.1:{}", port));
     let address = format!("http://127.0.0.1:{}", application.port());
-    let _ = tokio::spawn(application.run_until_stopped());
+    let email_client = configuration
+        .email_client
+        .client()
+        .with_metrics(application.metrics());
+    let shutdown = CancellationToken::new();
+    let application = tokio::spawn(application.run_until(shutdown.clone().cancelled_owned()));
+
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
 
-    TestApp {
+    let test_app = TestApp {
         port: application_port,
         address,
+        admin_address,
+        shutdown,
+        application,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
-    }
+        captcha_server,
+        email_client,
+        base_url: configuration.application.base_url,
+        webhook_settings: configuration.webhooks,
+        test_user: TestUser::generate(),
+        api_client,
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
@@ -91,14 +174,84 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 pub struct TestApp {
     pub port: u16,
     pub address: String,
+    /// Where `/metrics` is served, if anywhere.
+    pub admin_address: Option<String>,
+    /// Cancel to shut the application down, as SIGTERM would.
+    pub shutdown: CancellationToken,
+    pub application: JoinHandle<Result<(), std::io::Error>>,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub captcha_server: MockServer,
+    /// Runs the background tasks, recording to the app's metrics.
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub webhook_settings: WebhookSettings,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
 }
 
 impl TestApp {
+    /// Drain the issue delivery queue, as the background worker would.
+    pub async fn dispatch_all_pending_emails(&self) {
+        while let ExecutionOutcome::TaskCompleted =
+            try_execute_task(&self.db_pool, &self.email_client, &self.base_url)
+                .await
+                .unwrap()
+        {}
+    }
+
+    /// Send, or give up on, every email due in the outbox, as the
+    /// dispatcher would.
+    pub async fn dispatch_outbox_emails(&self) {
+        while let ExecutionOutcome::TaskCompleted =
+            try_dispatch_email(&self.db_pool, &self.email_client)
+                .await
+                .unwrap()
+        {}
+    }
+
+    pub async fn get_readiness(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/health/ready", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        let address = self
+            .admin_address
+            .as_ref()
+            .expect("The app was spawned without an admin port.");
+        self.api_client
+            .get(format!("{}/metrics", address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/resend", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -106,6 +259,148 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Submit the form behind a confirmation link, as the subscriber would.
+    pub async fn post_confirmation(&self, confirmation_link: reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .post(confirmation_link)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_postmark_webhook(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.webhook_settings.username,
+                Some(self.webhook_settings.password.expose_secret()),
+            )
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_keys_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api_keys", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_api_keys<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api_keys", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Store a new API key, returning it.
+    pub async fn create_api_key(&self) -> String {
+        let (_, key) = create_api_key("test", &self.db_pool)
+            .await
+            .expect("Failed to create an API key.");
+        key.expose_secret().to_string()
+    }
+
+    /// A request to `/api/v1{path}`, authenticated with `api_key`.
+    pub fn api_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        api_key: &str,
+    ) -> reqwest::RequestBuilder {
+        self.api_client
+            .request(method, format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_key)
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -122,13 +417,99 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+    app.post_confirmation(confirmation_links.html)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
 }
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub async fn login(&self, app: &TestApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password
+        }))
+        .await;
+    }
+
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let salt = SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+        // Match the production parameters, so that `validate_credentials`
+        // spends the same time on a known and on an unknown username.
+        let password_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(15000, 2, 1, None).unwrap(),
+        )
+        .hash_password(self.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
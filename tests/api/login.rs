@@ -0,0 +1,96 @@
+use secrecy::SecretString;
+use zero2prod::configuration::InitialAdminSettings;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn login_form_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app.get_login_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"<form action="/login" method="post">"#));
+}
+
+#[tokio::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Try to login
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+
+    // Act - Part 3 - Reload the login page
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("<p><i>Authentication failed</i></p>"));
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Login
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_initial_admin_is_created_on_startup() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.initial_admin = Some(InitialAdminSettings {
+            username: "first-admin".into(),
+            password: SecretString::from("first-password"),
+        })
+    })
+    .await;
+
+    // Act
+    let login_body = serde_json::json!({
+        "username": "first-admin",
+        "password": "first-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn sessions_are_stored_server_side() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.test_user.login(&app).await;
+
+    // Assert
+    let n_sessions = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_sessions, 1);
+}
@@ -1,4 +1,18 @@
+mod admin_dashboard;
+mod api_keys;
+mod api_subscribers;
+mod change_password;
 mod health_check;
 mod helpers;
+mod login;
+mod metrics;
+mod newsletters;
+mod openapi;
+mod rate_limit;
+mod shutdown;
+mod subscription_cleanup;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
+mod webhooks;
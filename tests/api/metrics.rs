@@ -0,0 +1,105 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn metrics_are_served_on_the_admin_port() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_metrics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("db_pool_max_connections"));
+    assert!(body.contains("issue_delivery_queue_depth 0"));
+}
+
+#[tokio::test]
+async fn metrics_count_subscriptions_emails_and_requests() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    create_confirmed_subscriber(&app).await;
+
+    // Assert
+    let body = app.get_metrics().await.text().await.unwrap();
+    assert!(body.contains("subscriptions_created_total 1"));
+    assert!(body.contains("subscriptions_confirmed_total 1"));
+    assert!(body.contains(r#"emails_sent_total{provider="postmark"} 1"#));
+    assert!(
+        body.contains(
+            r#"http_requests_total{method="POST",route="/subscriptions",status="200"} 1"#
+        )
+    );
+    assert!(body.contains(
+        r#"http_request_duration_seconds_count{method="POST",route="/subscriptions/confirm"} 1"#
+    ));
+}
+
+#[tokio::test]
+async fn metrics_count_failed_emails() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_client.retry.max_attempts = 1).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    let body = app.get_metrics().await.text().await.unwrap();
+    assert!(body.contains(r#"emails_failed_total{provider="postmark"} 1"#));
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_on_the_main_port() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app.get_metrics().await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Requests to the main port are still counted
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#));
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_without_an_admin_port() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.admin_port = None).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(app.admin_address.is_none());
+}
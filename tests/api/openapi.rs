@@ -0,0 +1,120 @@
+use std::path::Path;
+
+use crate::helpers::spawn_app;
+
+/// Set to overwrite the committed spec with the one we serve.
+const UPDATE_ENV_VAR: &str = "UPDATE_OPENAPI_SPEC";
+
+#[tokio::test]
+async fn the_committed_openapi_spec_is_up_to_date() {
+    // Arrange
+    let app = spawn_app().await;
+    let committed_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let served: serde_json::Value = response.json().await.unwrap();
+    if std::env::var(UPDATE_ENV_VAR).is_ok() {
+        let mut spec = serde_json::to_string_pretty(&served).unwrap();
+        spec.push('\n');
+        std::fs::write(&committed_path, spec).expect("Failed to write openapi.json.");
+    }
+    let committed: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(&committed_path).expect("Failed to read openapi.json."),
+    )
+    .expect("openapi.json is not valid JSON.");
+    assert_eq!(
+        served, committed,
+        "openapi.json is out of date, run the tests with {}=1 to regenerate it.",
+        UPDATE_ENV_VAR
+    );
+}
+
+#[tokio::test]
+async fn the_openapi_spec_documents_the_subscription_endpoints() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let spec: serde_json::Value = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    let subscribe = &spec["paths"]["/subscriptions"]["post"];
+    let form = &subscribe["requestBody"]["content"]["application/x-www-form-urlencoded"];
+    assert_eq!(form["schema"]["$ref"], "#/components/schemas/FormData");
+    for status in ["200", "400", "429", "500"] {
+        assert!(
+            subscribe["responses"][status].is_object(),
+            "{} is missing.",
+            status
+        );
+    }
+    let confirm = &spec["paths"]["/subscriptions/confirm"];
+    for method in ["get", "post"] {
+        assert_eq!(
+            confirm[method]["parameters"][0]["name"],
+            "subscription_token"
+        );
+        assert!(confirm[method]["responses"]["410"].is_object());
+    }
+}
+
+/// Every `$ref` in `value` points at a schema in `spec`.
+fn assert_refs_resolve(spec: &serde_json::Value, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(reference) = map.get("$ref").and_then(|r| r.as_str()) {
+                let name = reference
+                    .strip_prefix("#/components/schemas/")
+                    .expect("Only schemas are referenced.");
+                assert!(
+                    spec["components"]["schemas"][name].is_object(),
+                    "{} is not defined.",
+                    reference
+                );
+            }
+            map.values().for_each(|v| assert_refs_resolve(spec, v));
+        }
+        serde_json::Value::Array(values) => {
+            values.iter().for_each(|v| assert_refs_resolve(spec, v))
+        }
+        _ => {}
+    }
+}
+
+#[tokio::test]
+async fn every_schema_referenced_by_the_openapi_spec_is_defined() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let spec: serde_json::Value = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_refs_resolve(&spec, &spec);
+}
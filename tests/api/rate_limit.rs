@@ -0,0 +1,226 @@
+use secrecy::SecretString;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{body_string_contains, method, path},
+};
+use zero2prod::configuration::CaptchaSettings;
+
+use crate::helpers::{TestApp, spawn_app_with};
+
+async fn post_subscriptions_from(app: &TestApp, client_ip: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Fly-Client-IP", client_ip)
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn mock_email_provider(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn clients_over_the_ip_limit_get_a_429_with_retry_after() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.rate_limit.requests_per_ip = 2).await;
+    mock_email_provider(&app).await;
+    for i in 0..2 {
+        let body = format!("name=le%20guin&email=ursula{}%40gmail.com", i);
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula2%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0);
+}
+
+#[tokio::test]
+async fn the_ip_limit_is_shared_with_the_resend_endpoint() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.rate_limit.requests_per_ip = 1).await;
+    mock_email_provider(&app).await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = app
+        .post_resend_confirmation("email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn requests_for_the_same_email_are_limited_whatever_the_client_ip() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.rate_limit.requests_per_email = 1;
+        c.application.rate_limit.trusted_proxy_headers = vec!["Fly-Client-IP".into()];
+    })
+    .await;
+    mock_email_provider(&app).await;
+    let response = post_subscriptions_from(
+        &app,
+        "203.0.113.1",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = post_subscriptions_from(
+        &app,
+        "203.0.113.2",
+        "name=le%20guin&email=Ursula_Le_Guin%40gmail.com",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_limited_separately() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.rate_limit.requests_per_ip = 1;
+        c.application.rate_limit.trusted_proxy_headers = vec!["Fly-Client-IP".into()];
+    })
+    .await;
+    mock_email_provider(&app).await;
+    let response = post_subscriptions_from(
+        &app,
+        "203.0.113.1",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = post_subscriptions_from(
+        &app,
+        "203.0.113.2",
+        "name=tolkien&email=jrr_tolkien%40gmail.com",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn proxy_headers_are_ignored_unless_trusted() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.rate_limit.requests_per_ip = 1).await;
+    mock_email_provider(&app).await;
+    let response = post_subscriptions_from(
+        &app,
+        "203.0.113.1",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = post_subscriptions_from(
+        &app,
+        "203.0.113.2",
+        "name=tolkien&email=jrr_tolkien%40gmail.com",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn the_email_limit_applies_to_json_bodies() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.rate_limit.requests_per_email = 1).await;
+    mock_email_provider(&app).await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn requests_failing_the_captcha_do_not_use_up_the_email_limit() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.rate_limit.requests_per_email = 1;
+        c.captcha = Some(CaptchaSettings {
+            verify_url: String::new(),
+            secret_key: SecretString::from("captcha-secret"),
+            timeout_milliseconds: 1000,
+        });
+    })
+    .await;
+    mock_email_provider(&app).await;
+    Mock::given(path("/siteverify"))
+        .and(body_string_contains("response=forged"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": false
+        })))
+        .mount(&app.captcha_server)
+        .await;
+    Mock::given(path("/siteverify"))
+        .and(body_string_contains("response=a-solved-captcha"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true
+        })))
+        .mount(&app.captcha_server)
+        .await;
+    for _ in 0..2 {
+        let response = app
+            .post_subscriptions(
+                "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=forged".into(),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=a-solved-captcha"
+                .into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, spawn_app_with};
+
+/// Readiness checks wait on the email provider: they give us a request that
+/// stays in flight for as long as the provider takes to answer.
+fn spawn_readiness_check(app: &TestApp) -> JoinHandle<reqwest::Result<reqwest::Response>> {
+    tokio::spawn(reqwest::get(format!("{}/health/ready", &app.address)))
+}
+
+/// Wait until the request we sent is being handled, blocked on the email
+/// provider's probe.
+async fn wait_for_email_request(app: &TestApp) {
+    while app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty()
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn in_flight_requests_complete_during_shutdown() {
+    // Arrange
+    let app = spawn_app_with(|c| c.health.probe_email_provider = true).await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let in_flight = spawn_readiness_check(&app);
+    wait_for_email_request(&app).await;
+
+    // Act
+    app.shutdown.cancel();
+
+    // Assert
+    let response = in_flight.await.unwrap().unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.application.await.unwrap().unwrap();
+    // We no longer accept connections
+    let outcome = reqwest::get(format!("{}/health_check", &app.address)).await;
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn shutdown_gives_up_on_requests_after_the_grace_period() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.shutdown_grace_period_seconds = 1;
+        c.health.probe_email_provider = true;
+        c.health.timeout_milliseconds = 60_000;
+    })
+    .await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .mount(&app.email_server)
+        .await;
+
+    let in_flight = spawn_readiness_check(&app);
+    wait_for_email_request(&app).await;
+
+    // Act
+    app.shutdown.cancel();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(5), app.application)
+        .await
+        .expect("The application did not stop within the grace period.")
+        .unwrap()
+        .unwrap();
+    assert!(in_flight.await.unwrap().is_err());
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use zero2prod::subscription_cleanup_worker::purge_unconfirmed_subscriptions;
+
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+
+const RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[tokio::test]
+async fn cleanup_purges_abandoned_pending_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET subscribed_at = now() - interval '8 days';
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = purge_unconfirmed_subscriptions(&app.db_pool, RETENTION)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(outcome.n_deleted_tokens, 1);
+    assert_eq!(outcome.n_deleted_subscribers, 1);
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn cleanup_keeps_tokens_within_retention_and_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    // Expired, but recently enough to still tell the subscriber so
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES (gen_random_uuid(), 'pending@example.com', 'pending', now() - interval '8 days',
+            'pending_confirmation', 'pending-unsubscribe-token');
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscriber_id, subscription_token, expires_at)
+        SELECT id, 'pending-token', now() - interval '1 day'
+        FROM subscriptions WHERE email = 'pending@example.com';
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = purge_unconfirmed_subscriptions(&app.db_pool, RETENTION)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(outcome.n_deleted_tokens, 0);
+    assert_eq!(outcome.n_deleted_subscribers, 0);
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 2);
+}
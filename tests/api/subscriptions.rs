@@ -1,22 +1,18 @@
+use serde_json::json;
 use wiremock::{
     Mock, ResponseTemplate,
-    matchers::{method, path},
+    matchers::{body_string_contains, method, path},
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, spawn_app_with_captcha,
+};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
     // Arrange
     let app = spawn_app().await;
 
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .expect(1)
-        .mount(&app.email_server)
-        .await;
-
     // Act
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
     let response = app.post_subscriptions(body.into()).await;
@@ -92,6 +88,167 @@ async fn subscribe_returns_a_400_when_data_is_missing() {
     }
 }
 
+#[tokio::test]
+async fn subscribe_accepts_json_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    app.dispatch_outbox_emails().await;
+}
+
+/// Check that `response` is a 400 problem document, returning it.
+async fn assert_is_bad_request_problem(response: reqwest::Response) -> serde_json::Value {
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["status"], 400);
+    assert!(problem["detail"].is_string());
+    problem
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_malformed_json() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (r#"{"name": "le guin"}"#, "missing the email"),
+        (
+            r#"{"email": "ursula_le_guin@gmail.com"}"#,
+            "missing the name",
+        ),
+        (
+            r#"{"name": 42, "email": "ursula_le_guin@gmail.com"}"#,
+            "a number as the name",
+        ),
+        (r#"{"name": "le guin", "email": "#, "a syntax error"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = reqwest::Client::new()
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+        assert_is_bad_request_problem(response).await;
+    }
+}
+
+#[tokio::test]
+async fn invalid_fields_are_described_by_a_problem_document_for_json_clients() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&json!({
+            "name": "le guin",
+            "email": "definitely-not-an-email"
+        }))
+        .await;
+
+    // Assert
+    let problem = assert_is_bad_request_problem(response).await;
+    assert_eq!(problem["invalid-params"][0]["name"], "email");
+    assert!(problem["invalid-params"][0]["reason"].is_string());
+    assert_eq!(problem["invalid-params"][0]["code"], "invalid_syntax");
+}
+
+#[tokio::test]
+async fn every_invalid_field_is_reported_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&json!({
+            "name": "le guin{}",
+            "email": ""
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    let invalid_params = problem["invalid-params"].as_array().unwrap();
+    assert_eq!(invalid_params.len(), 2);
+    assert_eq!(invalid_params[0]["name"], "name");
+    assert_eq!(invalid_params[0]["code"], "forbidden_characters");
+    assert_eq!(invalid_params[1]["name"], "email");
+    assert_eq!(invalid_params[1]["code"], "empty");
+}
+
+#[tokio::test]
+async fn the_accept_header_decides_the_format_of_validation_errors() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("application/json", "application/problem+json"),
+        ("application/problem+json", "application/problem+json"),
+        ("text/html, application/json;q=0.9", "text/plain"),
+        ("*/*", "text/plain"),
+    ];
+
+    for (accept, expected_content_type) in test_cases {
+        // Act
+        let response = reqwest::Client::new()
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", accept)
+            .body("name=&email=ursula_le_guin%40gmail.com")
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(400, response.status().as_u16());
+        let content_type = response.headers()["Content-Type"].to_str().unwrap();
+        assert!(
+            content_type.starts_with(expected_content_type),
+            "Expected {} for Accept: {}, got {}.",
+            expected_content_type,
+            accept,
+            content_type
+        );
+    }
+}
+
 #[tokio::test]
 async fn subscribe_sends_a_confirmation_email_for_valid_data() {
     //Arrange
@@ -107,6 +264,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
 
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     //Assert
     // Mock asserts on drop
@@ -127,13 +285,135 @@ async fn subscribe_sends_a_confirmation_email_with_link() {
 
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     // Assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn subscribe_propagates_the_trace_context_to_the_email_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header(
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", trace_id),
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request.headers["traceparent"].to_str().unwrap();
+    // Same trace, but the parent is now one of our spans
+    assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+    assert!(!traceparent.contains("00f067aa0ba902b7"));
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_while_the_email_provider_is_down() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT recipient FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The confirmation email is no longer in the outbox.");
+    assert_eq!(saved.recipient, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_retried_on_transient_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+    // Skip the backoff
+    sqlx::query!("UPDATE email_outbox SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    // Mock verifies on drop that we have sent the confirmation email twice
+}
+
+#[tokio::test]
+async fn sent_emails_are_removed_from_the_outbox() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    let n_emails = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_emails, 0);
+}
+
 #[tokio::test]
 async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Arrange
@@ -151,3 +431,246 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_new_confirmation_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let second_links = create_unconfirmed_subscriber(&app).await;
+
+    // Assert
+    assert_ne!(first_links.html, second_links.html);
+    let response = app.post_confirmation(first_links.html).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_confirmation(second_links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_returns_200_without_sending_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_can_subscribe_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn illegal_status_transitions_are_rejected_by_the_database() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    for status in ["pending_confirmation", "not_a_status"] {
+        // Act
+        let outcome = sqlx::query!("UPDATE subscriptions SET status = $1", status)
+            .execute(&app.db_pool)
+            .await;
+
+        // Assert
+        assert!(
+            outcome.is_err(),
+            "The database let a confirmed subscriber go to {}.",
+            status
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_ignores_forms_filling_in_the_honeypot() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=spam.example.com";
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_solved_captchas() {
+    // Arrange
+    let app = spawn_app_with_captcha().await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("secret=captcha-secret"))
+        .and(body_string_contains("response=a-solved-captcha"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": true })))
+        .expect(2)
+        .mount(&app.captcha_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for token_field in ["h-captcha-response", "cf-turnstile-response"] {
+        let body = format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&{}=a-solved-captcha",
+            token_field
+        );
+        let response = app.post_subscriptions(body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "Rejected a token sent as {}.",
+            token_field
+        );
+    }
+    app.dispatch_outbox_emails().await;
+}
+
+#[tokio::test]
+async fn subscribe_rejects_missing_or_failed_captchas() {
+    // Arrange
+    let app = spawn_app_with_captcha().await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .mount(&app.captcha_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let test_cases = [
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com",
+            "no token",
+        ),
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=forged",
+            "a token the provider rejects",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body.into()).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Accepted a form with {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn failed_captchas_are_described_by_a_problem_document_for_json_clients() {
+    // Arrange
+    let app = spawn_app_with_captcha().await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .mount(&app.captcha_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "h-captcha-response": "forged"
+        }))
+        .await;
+
+    // Assert
+    let problem = assert_is_bad_request_problem(response).await;
+    assert_eq!(problem["detail"], "The CAPTCHA was not solved.");
+}
+
+#[tokio::test]
+async fn subscribe_fails_if_the_captcha_provider_is_down() {
+    // Arrange
+    let app = spawn_app_with_captcha().await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.captcha_server)
+        .await;
+
+    // Act
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=a-token";
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+}
@@ -3,7 +3,9 @@ use wiremock::{
     matchers::{method, path},
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    assert_is_redirect_to, create_unconfirmed_subscriber, spawn_app, spawn_app_with,
+};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -32,8 +34,9 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // Act
     let response = reqwest::get(confirmation_links.html).await.unwrap();
@@ -42,6 +45,28 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
     assert_eq!(response.status().as_u16(), 200);
 }
 
+#[tokio::test]
+async fn following_the_confirmation_link_does_not_confirm_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act - Link scanners may fetch it any number of times
+    for _ in 0..2 {
+        let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+    let response = app.post_confirmation(confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
 #[tokio::test]
 async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     // Arrange
@@ -55,14 +80,29 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
-    // Act
-    reqwest::get(confirmation_links.plain_text)
+    // Act - Part 1 - Follow the link
+    let html_page = reqwest::get(confirmation_links.plain_text.clone())
         .await
         .unwrap()
         .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<form action="{}?{}" method="post">"#,
+        confirmation_links.plain_text.path(),
+        confirmation_links.plain_text.query().unwrap()
+    )));
+
+    // Act - Part 2 - Submit the form
+    app.post_confirmation(confirmation_links.plain_text)
+        .await
+        .error_for_status()
         .unwrap();
 
     // Assert
@@ -74,3 +114,143 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
 }
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    let response = app.post_confirmation(confirmation_links.html.clone()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+    // Submitting again gives the same answer
+    let response = app.post_confirmation(confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn confirmation_links_stop_working_once_the_address_bounced() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_confirmation(confirmation_links.html).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "bounced");
+}
+
+#[tokio::test]
+async fn confirming_renders_a_success_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.post_confirmation(confirmation_links.html).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Thanks for confirming your subscription"));
+}
+
+#[tokio::test]
+async fn confirming_redirects_to_the_configured_url() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscriptions.confirmation_redirect_url = Some("https://example.com/welcome".into())
+    })
+    .await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.post_confirmation(confirmation_links.html).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "https://example.com/welcome");
+}
+
+#[tokio::test]
+async fn unknown_tokens_render_an_invalid_link_page() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link is not valid"));
+}
+
+#[tokio::test]
+async fn expired_tokens_render_a_page_to_get_a_new_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has expired"));
+    assert!(html_page.contains(r#"<form action="/subscriptions/resend" method="post">"#));
+}
+
+#[tokio::test]
+async fn unexpected_errors_render_an_error_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    // Sabotage the database
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN expires_at;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Something went wrong"));
+}
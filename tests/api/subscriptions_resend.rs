@@ -0,0 +1,84 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+
+#[tokio::test]
+async fn resend_sends_a_new_confirmation_link_to_pending_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_resend_confirmation("email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let second_links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(first_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(second_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resend_does_not_send_emails_to_unknown_or_confirmed_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["ursula_le_guin%40gmail.com", "someone_else%40gmail.com"] {
+        // Act
+        let response = app
+            .post_resend_confirmation(format!("email={}", email))
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_outbox_emails().await;
+}
+
+#[tokio::test]
+async fn resend_returns_a_400_for_an_invalid_email() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_resend_confirmation("email=not-an-email".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,245 @@
+use uuid::Uuid;
+use wiremock::{Mock, ResponseTemplate, matchers::any};
+
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+
+const SUBSCRIBER_EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+fn bounce(bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807u64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "Email": SUBSCRIBER_EMAIL,
+        "BouncedAt": "2025-06-23T10:00:00Z",
+        "Description": "The server was unable to deliver your message.",
+    })
+}
+
+#[tokio::test]
+async fn webhook_calls_without_valid_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for password in [None, Some(Uuid::new_v4().to_string())] {
+        // Act
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/webhooks/postmark", &app.address))
+            .json(&bounce("HardBounce"));
+        if let Some(password) = password {
+            request = request.basic_auth(&app.webhook_settings.username, Some(password));
+        }
+        let response = request.send().await.unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            r#"Basic realm="webhooks""#,
+            response.headers()["WWW-Authenticate"]
+        );
+    }
+}
+
+#[tokio::test]
+async fn hard_bounces_mark_the_subscriber_as_bounced_and_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.post_postmark_webhook(bounce("HardBounce")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    let event = sqlx::query!("SELECT record_type, email FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the recorded event.");
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.email, SUBSCRIBER_EMAIL);
+}
+
+#[tokio::test]
+async fn bounces_match_subscribers_regardless_of_the_case_of_their_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut bounce = bounce("HardBounce");
+    bounce["Email"] = SUBSCRIBER_EMAIL.to_uppercase().into();
+
+    // Act
+    let response = app.post_postmark_webhook(bounce).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+}
+
+#[tokio::test]
+async fn soft_bounces_are_recorded_without_changing_the_status() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.post_postmark_webhook(bounce("SoftBounce")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let n_events = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 1);
+}
+
+#[tokio::test]
+async fn spam_complaints_mark_the_subscriber_as_complained() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "Email": SUBSCRIBER_EMAIL,
+            "BouncedAt": "2025-06-23T10:00:00Z",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[tokio::test]
+async fn subscription_changes_follow_the_suppression_reason() {
+    let test_cases = vec![
+        ("HardBounce", "bounced"),
+        ("SpamComplaint", "complained"),
+        ("ManualSuppression", "unsubscribed"),
+    ];
+    for (reason, expected_status) in test_cases {
+        // Arrange
+        let app = spawn_app().await;
+        create_confirmed_subscriber(&app).await;
+
+        // Act
+        let response = app
+            .post_postmark_webhook(serde_json::json!({
+                "RecordType": "SubscriptionChange",
+                "Recipient": SUBSCRIBER_EMAIL,
+                "SuppressSending": true,
+                "SuppressionReason": reason,
+                "ChangedAt": "2025-06-23T10:00:00Z",
+            }))
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(
+            subscriber_status(&app).await,
+            expected_status,
+            "Unexpected status for suppression reason {}.",
+            reason
+        );
+    }
+}
+
+#[tokio::test]
+async fn other_record_types_are_acknowledged_and_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(serde_json::json!({
+            "RecordType": "Open",
+            "Recipient": SUBSCRIBER_EMAIL,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let n_events = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 0);
+}
+
+#[tokio::test]
+async fn bounced_subscribers_do_not_receive_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_postmark_webhook(bounce("HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn suppressions_of_bounced_addresses_leave_them_bounced() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_postmark_webhook(bounce("HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_postmark_webhook(serde_json::json!({
+            "RecordType": "SubscriptionChange",
+            "Recipient": SUBSCRIBER_EMAIL,
+            "SuppressSending": true,
+            "SuppressionReason": "ManualSuppression",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    let n_events = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 2);
+}